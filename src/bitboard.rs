@@ -0,0 +1,245 @@
+use crate::{Board, Color, PieceType, Position};
+
+/// A set of squares, bit `8 * y + x` is the square at `(x, y)`
+pub type Bitboard = u64;
+
+/// Index of a position in a bitboard
+pub fn square(position: Position) -> usize {
+    (position.y * 8 + position.x) as usize
+}
+
+pub fn position(square: usize) -> Position {
+    ((square % 8) as i8, (square / 8) as i8).into()
+}
+
+pub fn bit(position: Position) -> Bitboard {
+    1 << square(position)
+}
+
+/// Iterates over the squares in a bitboard, lowest first
+pub fn squares(mut bitboard: Bitboard) -> impl Iterator<Item = usize> {
+    std::iter::from_fn(move || {
+        if bitboard == 0 {
+            return None;
+        }
+        let square = bitboard.trailing_zeros() as usize;
+        bitboard &= bitboard - 1;
+        Some(square)
+    })
+}
+
+const fn leaper_table<const N: usize>(deltas: [(i8, i8); N]) -> [Bitboard; 64] {
+    let mut table = [0; 64];
+    let mut square = 0;
+    while square < 64 {
+        let x = (square % 8) as i8;
+        let y = (square / 8) as i8;
+        let mut i = 0;
+        while i < deltas.len() {
+            let (nx, ny) = (x + deltas[i].0, y + deltas[i].1);
+            if nx >= 0 && nx < 8 && ny >= 0 && ny < 8 {
+                table[square] |= 1 << (ny * 8 + nx);
+            }
+            i += 1;
+        }
+        square += 1;
+    }
+    table
+}
+
+pub const KNIGHT_ATTACKS: [Bitboard; 64] = leaper_table([
+    (1, 2),
+    (2, 1),
+    (2, -1),
+    (1, -2),
+    (-1, -2),
+    (-2, -1),
+    (-2, 1),
+    (-1, 2),
+]);
+
+pub const KING_ATTACKS: [Bitboard; 64] = leaper_table([
+    (0, 1),
+    (1, 1),
+    (1, 0),
+    (1, -1),
+    (0, -1),
+    (-1, -1),
+    (-1, 0),
+    (-1, 1),
+]);
+
+/// Squares a pawn of each color attacks, indexed by `Color as usize` and then square
+pub const PAWN_ATTACKS: [[Bitboard; 64]; 2] = [
+    leaper_table([(-1, 1), (1, 1)]),
+    leaper_table([(-1, -1), (1, -1)]),
+];
+
+/// Ray directions, the first four go towards higher squares
+const DIRECTIONS: [(i8, i8); 8] = [
+    (0, 1),
+    (1, 1),
+    (1, 0),
+    (-1, 1),
+    (0, -1),
+    (-1, -1),
+    (-1, 0),
+    (1, -1),
+];
+
+/// Every square in a direction from a square until the edge of the board
+const RAYS: [[Bitboard; 64]; 8] = {
+    let mut rays = [[0; 64]; 8];
+    let mut direction = 0;
+    while direction < 8 {
+        let (dx, dy) = DIRECTIONS[direction];
+        let mut square = 0;
+        while square < 64 {
+            let mut x = (square % 8) as i8 + dx;
+            let mut y = (square / 8) as i8 + dy;
+            while x >= 0 && x < 8 && y >= 0 && y < 8 {
+                rays[direction][square] |= 1 << (y * 8 + x);
+                x += dx;
+                y += dy;
+            }
+            square += 1;
+        }
+        direction += 1;
+    }
+    rays
+};
+
+/// Squares strictly between two squares on the same line, empty if they aren't lined up
+pub static BETWEEN: [[Bitboard; 64]; 64] = lines().0;
+
+/// The whole line through two squares from edge to edge, empty if they aren't lined up
+pub static LINE: [[Bitboard; 64]; 64] = lines().1;
+
+const fn lines() -> ([[Bitboard; 64]; 64], [[Bitboard; 64]; 64]) {
+    let mut between = [[0; 64]; 64];
+    let mut line = [[0; 64]; 64];
+    let mut from = 0;
+    while from < 64 {
+        let mut direction = 0;
+        while direction < 8 {
+            let (dx, dy) = DIRECTIONS[direction];
+            let full = RAYS[direction][from] | RAYS[(direction + 4) % 8][from] | 1 << from;
+            let mut x = (from % 8) as i8 + dx;
+            let mut y = (from / 8) as i8 + dy;
+            let mut passed = 0;
+            while x >= 0 && x < 8 && y >= 0 && y < 8 {
+                let to = (y * 8 + x) as usize;
+                between[from][to] = passed;
+                line[from][to] = full;
+                passed |= 1 << to;
+                x += dx;
+                y += dy;
+            }
+            direction += 1;
+        }
+        from += 1;
+    }
+    (between, line)
+}
+
+/// Squares along a ray up to and including the first piece in the way
+fn ray_attacks(direction: usize, square: usize, occupied: Bitboard) -> Bitboard {
+    let ray = RAYS[direction][square];
+    let blockers = ray & occupied;
+    if blockers == 0 {
+        return ray;
+    }
+
+    let blocker = match direction < 4 {
+        true => blockers.trailing_zeros() as usize,
+        false => 63 - blockers.leading_zeros() as usize,
+    };
+    ray ^ RAYS[direction][blocker]
+}
+
+pub fn rook_attacks(square: usize, occupied: Bitboard) -> Bitboard {
+    [0, 2, 4, 6].iter().fold(0, |attacks, direction| {
+        attacks | ray_attacks(*direction, square, occupied)
+    })
+}
+
+pub fn bishop_attacks(square: usize, occupied: Bitboard) -> Bitboard {
+    [1, 3, 5, 7].iter().fold(0, |attacks, direction| {
+        attacks | ray_attacks(*direction, square, occupied)
+    })
+}
+
+pub fn queen_attacks(square: usize, occupied: Bitboard) -> Bitboard {
+    rook_attacks(square, occupied) | bishop_attacks(square, occupied)
+}
+
+/// The squares attacked by a piece, not counting pawn pushes
+pub fn attacks(piece_type: PieceType, color: Color, square: usize, occupied: Bitboard) -> Bitboard {
+    match piece_type {
+        PieceType::Pawn => PAWN_ATTACKS[color as usize][square],
+        PieceType::Knight => KNIGHT_ATTACKS[square],
+        PieceType::Bishop => bishop_attacks(square, occupied),
+        PieceType::Rook => rook_attacks(square, occupied),
+        PieceType::Queen => queen_attacks(square, occupied),
+        PieceType::King => KING_ATTACKS[square],
+    }
+}
+
+/// Where every piece is, one bitboard per piece type and one per color.
+/// Kept in sync with the `pieces` array of a `Game`
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Bitboards {
+    /// Indexed by `PieceType as usize`
+    pub by_type: [Bitboard; 6],
+    /// Indexed by `Color as usize`
+    pub by_color: [Bitboard; 2],
+}
+
+impl Bitboards {
+    pub fn from_board(board: &Board) -> Self {
+        let mut bitboards = Bitboards::default();
+        for piece in board.iter().flatten().flatten() {
+            bitboards.toggle(piece.color, piece.piece_type, square(piece.position));
+        }
+        bitboards
+    }
+
+    pub fn occupied(&self) -> Bitboard {
+        self.by_color[0] | self.by_color[1]
+    }
+
+    pub fn pieces(&self, color: Color, piece_type: PieceType) -> Bitboard {
+        self.by_color[color as usize] & self.by_type[piece_type as usize]
+    }
+
+    /// Adds a piece to an empty square, or removes it if it is there
+    pub fn toggle(&mut self, color: Color, piece_type: PieceType, square: usize) {
+        self.by_color[color as usize] ^= 1 << square;
+        self.by_type[piece_type as usize] ^= 1 << square;
+    }
+
+    /// Pieces of both colors attacking a square, with the board occupied as given
+    pub fn attackers(&self, square: usize, occupied: Bitboard) -> Bitboard {
+        let diagonal =
+            self.by_type[PieceType::Bishop as usize] | self.by_type[PieceType::Queen as usize];
+        let straight =
+            self.by_type[PieceType::Rook as usize] | self.by_type[PieceType::Queen as usize];
+        let pawns = self.by_type[PieceType::Pawn as usize];
+
+        // Attacks are symmetric, so look from the square with each kind of piece instead.
+        // Pawns are the exception, a white pawn attacks where a black pawn would be attacked from
+        PAWN_ATTACKS[Color::Black as usize][square] & pawns & self.by_color[Color::White as usize]
+            | PAWN_ATTACKS[Color::White as usize][square]
+                & pawns
+                & self.by_color[Color::Black as usize]
+            | KNIGHT_ATTACKS[square] & self.by_type[PieceType::Knight as usize]
+            | KING_ATTACKS[square] & self.by_type[PieceType::King as usize]
+            | bishop_attacks(square, occupied) & diagonal
+            | rook_attacks(square, occupied) & straight
+    }
+
+    /// Whether any piece of a color attacks a square, with the board occupied as given
+    pub fn is_attacked(&self, square: usize, by: Color, occupied: Bitboard) -> bool {
+        self.attackers(square, occupied) & self.by_color[by as usize] != 0
+    }
+}
@@ -0,0 +1,100 @@
+use crate::{fen::FenError, Game, PieceType};
+
+/// Number of Chess960 starting positions
+pub const POSITIONS: u16 = 960;
+
+/// Index of the standard starting position
+pub const STANDARD_POSITION: u16 = 518;
+
+/// Where the two knights go among the five squares left after the bishops and queen
+const KNIGHTS: [(usize, usize); 10] = [
+    (0, 1),
+    (0, 2),
+    (0, 3),
+    (0, 4),
+    (1, 2),
+    (1, 3),
+    (1, 4),
+    (2, 3),
+    (2, 4),
+    (3, 4),
+];
+
+/// The back rank of a Chess960 starting position from the a to the h file, using the
+/// standard numbering from 0 to 959. None if the index is out of range
+pub fn back_rank(index: u16) -> Option<[PieceType; 8]> {
+    if index >= POSITIONS {
+        return None;
+    }
+
+    let mut rank = [None; 8];
+    let mut n = index as usize;
+
+    // Bishops go on opposite colors, the light squared one on b, d, f or h
+    rank[n % 4 * 2 + 1] = Some(PieceType::Bishop);
+    n /= 4;
+    rank[n % 4 * 2] = Some(PieceType::Bishop);
+    n /= 4;
+
+    // Everything else is counted in the squares that are still empty
+    let place = |rank: &mut [Option<PieceType>; 8], nth: usize, piece_type: PieceType| {
+        let x = (0..8).filter(|x| rank[*x].is_none()).nth(nth).unwrap();
+        rank[x] = Some(piece_type);
+    };
+    place(&mut rank, n % 6, PieceType::Queen);
+    n /= 6;
+
+    // The second knight is placed after the first one, which takes an empty square away
+    let (first, second) = KNIGHTS[n];
+    place(&mut rank, first, PieceType::Knight);
+    place(&mut rank, second - 1, PieceType::Knight);
+
+    // Rook, king and rook in the last three, so the king always ends up between the rooks
+    for piece_type in [PieceType::Rook, PieceType::King, PieceType::Rook] {
+        place(&mut rank, 0, piece_type);
+    }
+
+    Some(rank.map(Option::unwrap))
+}
+
+/// FEN of a Chess960 starting position, with the castling rights as Shredder-FEN
+pub fn fen(index: u16) -> Option<String> {
+    let rank = back_rank(index)?;
+    let white: String = rank.iter().map(|piece_type| piece_type.to_char()).collect();
+    let black = white.to_ascii_lowercase();
+
+    // Rook files, kingside first
+    let mut rooks: Vec<char> = (0..8)
+        .filter(|x| rank[*x] == PieceType::Rook)
+        .map(|x| (b'A' + x as u8) as char)
+        .collect();
+    rooks.reverse();
+    let castling: String = rooks.iter().collect();
+
+    Some(format!(
+        "{}/pppppppp/8/8/8/8/PPPPPPPP/{} w {}{} - 0 1",
+        black,
+        white,
+        castling,
+        castling.to_ascii_lowercase()
+    ))
+}
+
+impl Game {
+    /// Sets up a Chess960 starting position, returns false if the index isn't below 960
+    pub fn load_chess960(&mut self, index: u16) -> bool {
+        match fen(index) {
+            Some(fen) => {
+                self.load_fen(&fen).unwrap();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Loads a Chess960 position. Castling can be Shredder-FEN or X-FEN, where `KQkq` are
+    /// the outermost rooks
+    pub fn load_chess960_fen(&mut self, fen: &str) -> Result<(), FenError> {
+        self.load_fen_as(fen, true)
+    }
+}
@@ -0,0 +1,374 @@
+use crate::{Color, Game};
+
+use std::{
+    fmt,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+/// Where a clock gets the current time from. Only differences between readings matter,
+/// so any starting point works
+pub trait TimeSource: fmt::Debug + Send + Sync {
+    fn now(&self) -> Duration;
+}
+
+/// The actual time, counted from when the source was created
+#[derive(Debug)]
+pub struct RealTime {
+    start: Instant,
+}
+
+impl RealTime {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Default for RealTime {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TimeSource for RealTime {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
+
+/// Time that only moves when told to, for tests and replaying games
+#[derive(Debug, Default)]
+pub struct ManualTime {
+    nanos: AtomicU64,
+}
+
+impl ManualTime {
+    pub fn advance(&self, duration: Duration) {
+        self.nanos
+            .fetch_add(duration.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    pub fn set(&self, now: Duration) {
+        self.nanos.store(now.as_nanos() as u64, Ordering::Relaxed);
+    }
+}
+
+impl TimeSource for ManualTime {
+    fn now(&self) -> Duration {
+        Duration::from_nanos(self.nanos.load(Ordering::Relaxed))
+    }
+}
+
+/// What happens to the clock on every move
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Timing {
+    SuddenDeath,
+    /// Added after every move
+    Fischer(Duration),
+    /// Time used on a move is given back afterwards, up to this much
+    Bronstein(Duration),
+    /// Simple (US) delay, the clock waits this long every move before it starts counting down
+    Delay(Duration),
+}
+
+/// Part of a time control, like the first 40 moves in 90 minutes of 40/90+30
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stage {
+    /// Moves to make before the next stage, None for the rest of the game
+    pub moves: Option<u32>,
+    /// Added to the clock when the stage starts
+    pub time: Duration,
+    pub timing: Timing,
+}
+
+/// The stages of a time control in order. If the last stage has a move count it
+/// starts over every time it is finished
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimeControl {
+    pub stages: Vec<Stage>,
+}
+
+impl TimeControl {
+    /// A single stage for the whole game
+    pub fn single(time: Duration, timing: Timing) -> Self {
+        Self {
+            stages: vec![Stage {
+                moves: None,
+                time,
+                timing,
+            }],
+        }
+    }
+
+    pub fn sudden_death(time: Duration) -> Self {
+        Self::single(time, Timing::SuddenDeath)
+    }
+
+    pub fn fischer(time: Duration, increment: Duration) -> Self {
+        Self::single(time, Timing::Fischer(increment))
+    }
+
+    pub fn bronstein(time: Duration, delay: Duration) -> Self {
+        Self::single(time, Timing::Bronstein(delay))
+    }
+
+    pub fn delay(time: Duration, delay: Duration) -> Self {
+        Self::single(time, Timing::Delay(delay))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ParseTimeControlError;
+
+impl fmt::Display for ParseTimeControlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid time control")
+    }
+}
+
+impl std::error::Error for ParseTimeControlError {}
+
+/// Parses the PGN `TimeControl` tag format, stages like `40/5400+30` separated by colons,
+/// in seconds. Delays can't be written in it
+impl FromStr for TimeControl {
+    type Err = ParseTimeControlError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let seconds = |field: &str| {
+            field
+                .parse::<u64>()
+                .map(Duration::from_secs)
+                .map_err(|_| ParseTimeControlError)
+        };
+
+        let mut stages = vec![];
+        for field in s.split(':') {
+            let (moves, rest) = match field.split_once('/') {
+                Some((moves, rest)) => (
+                    Some(moves.parse().map_err(|_| ParseTimeControlError)?),
+                    rest,
+                ),
+                None => (None, field),
+            };
+            let (time, timing) = match rest.split_once('+') {
+                Some((time, increment)) => (time, Timing::Fischer(seconds(increment)?)),
+                None => (rest, Timing::SuddenDeath),
+            };
+            if moves == Some(0) {
+                return Err(ParseTimeControlError);
+            }
+            stages.push(Stage {
+                moves,
+                time: seconds(time)?,
+                timing,
+            });
+        }
+
+        // Only the last stage can go on for the rest of the game
+        if stages[..stages.len() - 1]
+            .iter()
+            .any(|stage| stage.moves.is_none())
+        {
+            return Err(ParseTimeControlError);
+        }
+        Ok(Self { stages })
+    }
+}
+
+/// One player's side of the clock from before a move, so a takeback can put it back
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Turn {
+    remaining: Duration,
+    stage: usize,
+    stage_moves: u32,
+}
+
+/// A chess clock for both players. Nothing runs until the clock is started or pressed
+#[derive(Debug, Clone)]
+pub struct Clock {
+    control: TimeControl,
+    /// Indexed by `Color as usize`, not counting the turn in progress
+    remaining: [Duration; 2],
+    /// Stage each player is in and how many moves they made in it
+    stage: [usize; 2],
+    stage_moves: [u32; 2],
+    /// Whose time is running and when their turn started
+    running: Option<(Color, Duration)>,
+    time: Arc<dyn TimeSource>,
+}
+
+impl Clock {
+    pub fn new(control: TimeControl) -> Self {
+        Self::with_time_source(control, Arc::new(RealTime::new()))
+    }
+
+    /// Panics if the time control has no stages
+    pub fn with_time_source(control: TimeControl, time: Arc<dyn TimeSource>) -> Self {
+        let start = control.stages[0].time;
+        Self {
+            control,
+            remaining: [start; 2],
+            stage: [0; 2],
+            stage_moves: [0; 2],
+            running: None,
+            time,
+        }
+    }
+
+    pub fn control(&self) -> &TimeControl {
+        &self.control
+    }
+
+    /// Starts the time of a player, stopping the other one
+    pub fn start(&mut self, color: Color) {
+        self.stop();
+        if self.flagged().is_none() {
+            self.running = Some((color, self.time.now()));
+        }
+    }
+
+    /// Stops the clock, keeping the time used so far in the current turn
+    pub fn stop(&mut self) {
+        if let Some((color, since)) = self.running.take() {
+            self.remaining[color as usize] = self.remaining_at(color, since);
+        }
+    }
+
+    /// Whose time is running
+    pub fn running(&self) -> Option<Color> {
+        self.running.map(|(color, _)| color)
+    }
+
+    /// Ends the turn of a player after they moved and starts the opponent's time.
+    /// Nothing is added if the flag already fell. If it was the opponent's time running
+    /// they are charged for it before it starts over
+    pub fn press(&mut self, color: Color) {
+        let now = self.time.now();
+        if let Some((running, since)) = self.running {
+            if running != color {
+                self.remaining[running as usize] = self.remaining_at(running, since);
+            } else {
+                let index = color as usize;
+                let stage = self.control.stages[self.stage[index]];
+                let elapsed = now.saturating_sub(since);
+
+                self.remaining[index] = self.remaining_at(color, since);
+                if self.remaining[index].is_zero() {
+                    self.running = None;
+                    return;
+                }
+
+                match stage.timing {
+                    Timing::Fischer(increment) => self.remaining[index] += increment,
+                    Timing::Bronstein(delay) => self.remaining[index] += elapsed.min(delay),
+                    Timing::SuddenDeath | Timing::Delay(_) => (),
+                }
+
+                self.stage_moves[index] += 1;
+                if Some(self.stage_moves[index]) == stage.moves {
+                    // The last stage goes around again
+                    self.stage[index] = (self.stage[index] + 1).min(self.control.stages.len() - 1);
+                    self.stage_moves[index] = 0;
+                    self.remaining[index] += self.control.stages[self.stage[index]].time;
+                }
+            }
+        }
+        self.running = Some((!color, now));
+    }
+
+    /// Time left for a player, counting the turn in progress
+    pub fn remaining(&self, color: Color) -> Duration {
+        match self.running {
+            Some((running, since)) if running == color => self.remaining_at(color, since),
+            _ => self.remaining[color as usize],
+        }
+    }
+
+    /// Moves left until the player reaches the next stage, None if there isn't one
+    pub fn moves_to_go(&self, color: Color) -> Option<u32> {
+        let index = color as usize;
+        let stage = self.control.stages[self.stage[index]];
+        stage.moves.map(|moves| moves - self.stage_moves[index])
+    }
+
+    /// The player who ran out of time, if any
+    pub fn flagged(&self) -> Option<Color> {
+        [Color::White, Color::Black]
+            .into_iter()
+            .find(|color| self.remaining(*color).is_zero())
+    }
+
+    /// The player's side of the clock right now, saved before every move
+    pub(crate) fn turn(&self, color: Color) -> Turn {
+        Turn {
+            remaining: self.remaining(color),
+            stage: self.stage[color as usize],
+            stage_moves: self.stage_moves[color as usize],
+        }
+    }
+
+    /// Takes back a move of a player that was made with the clock at `turn`. The time they
+    /// used on it stays used, but the increment and stage it earned are taken away. The
+    /// opponent pays for the time since the move, then the player's time runs again
+    pub(crate) fn take_back(&mut self, color: Color, turn: Turn) {
+        let running = self.running.is_some();
+        self.stop();
+
+        let index = color as usize;
+        self.remaining[index] = turn.remaining;
+        self.stage[index] = turn.stage;
+        self.stage_moves[index] = turn.stage_moves;
+        if running {
+            self.start(color);
+        }
+    }
+
+    /// Time left for a player whose turn started at `since`
+    fn remaining_at(&self, color: Color, since: Duration) -> Duration {
+        let index = color as usize;
+        let elapsed = self.time.now().saturating_sub(since);
+        let used = match self.control.stages[self.stage[index]].timing {
+            Timing::Delay(delay) => elapsed.saturating_sub(delay),
+            _ => elapsed,
+        };
+        self.remaining[index].saturating_sub(used)
+    }
+}
+
+impl Game {
+    /// Puts a clock on the game, which gets pressed after every move. Loading a FEN
+    /// takes it off again
+    pub fn set_clock(&mut self, clock: Clock) {
+        self.clock = Some(clock);
+    }
+
+    pub fn clock(&self) -> Option<&Clock> {
+        self.clock.as_ref()
+    }
+
+    pub fn clock_mut(&mut self) -> Option<&mut Clock> {
+        self.clock.as_mut()
+    }
+
+    pub(crate) fn press_clock(&mut self, color: Color) {
+        if let Some(clock) = &mut self.clock {
+            // Saved with the move so `undo` can give it back
+            if let Some(entry) = self.history.last_mut() {
+                entry.clock = Some(clock.turn(color));
+            }
+            clock.press(color);
+        }
+    }
+
+    pub(crate) fn stop_clock(&mut self) {
+        if let Some(clock) = &mut self.clock {
+            clock.stop();
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use crate::{
+    bitboard,
+    moves::{check_bounds, IllegalMove, Move, MoveType},
+    Color, Game, GameStatus, Piece, PieceType, Position,
+};
+
+/// Pieces that can be dropped, in the order pockets are written in FEN
+pub const DROPPABLE: [PieceType; 5] = [
+    PieceType::Queen,
+    PieceType::Rook,
+    PieceType::Bishop,
+    PieceType::Knight,
+    PieceType::Pawn,
+];
+
+/// Captured pieces a player holds on to in Crazyhouse
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Pocket {
+    /// Indexed by `PieceType as usize`, kings never end up in here
+    counts: [u8; 6],
+}
+
+impl Pocket {
+    pub fn count(&self, piece_type: PieceType) -> u8 {
+        self.counts[piece_type as usize]
+    }
+
+    /// Most pieces of a type a pocket can hold in a real game, both players' worth.
+    /// Promoted pieces come back as pawns so there are never more than at the start
+    pub fn max(piece_type: PieceType) -> u8 {
+        match piece_type {
+            PieceType::Pawn => 16,
+            PieceType::Queen => 2,
+            PieceType::King => 0,
+            _ => 4,
+        }
+    }
+
+    /// Puts a piece in, returns false if the count can't go any higher
+    pub fn add(&mut self, piece_type: PieceType) -> bool {
+        let count = &mut self.counts[piece_type as usize];
+        match count.checked_add(1) {
+            Some(added) => {
+                *count = added;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Takes a piece out, returns false if there is none
+    pub fn take(&mut self, piece_type: PieceType) -> bool {
+        let count = &mut self.counts[piece_type as usize];
+        if *count == 0 {
+            return false;
+        }
+        *count -= 1;
+        true
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.counts.iter().all(|count| *count == 0)
+    }
+
+    /// Every piece type there is at least one of, queens first
+    pub fn piece_types(&self) -> impl Iterator<Item = PieceType> + '_ {
+        DROPPABLE
+            .into_iter()
+            .filter(|piece_type| self.count(*piece_type) > 0)
+    }
+}
+
+/// A piece coming out of the pocket, `from` is the same as `to` since it has no square yet
+pub(crate) fn drop_move(color: Color, piece_type: PieceType, to: Position) -> Move {
+    Move {
+        from: to,
+        to,
+        piece: Piece {
+            color,
+            piece_type,
+            position: to,
+            num_moves: 0,
+        },
+        captured: None,
+        promotion: None,
+        move_type: MoveType::Drop,
+        check: false,
+        checkmate: false,
+    }
+}
+
+impl Game {
+    pub fn pocket(&self, color: Color) -> Pocket {
+        self.pockets[color as usize]
+    }
+
+    /// Whether the piece on a square got there by promoting, it turns back into a pawn
+    /// when it is captured
+    pub fn is_promoted(&self, position: Position) -> bool {
+        check_bounds(position) && self.promoted & bitboard::bit(position) != 0
+    }
+
+    /// Checks a drop from the pocket of the player to move without playing it.
+    /// The returned move has no check flags
+    pub fn validate_drop(&self, piece_type: PieceType, to: Position) -> Result<Move, IllegalMove> {
+        if let GameStatus::Promotion(_) = self.status {
+            return Err(IllegalMove::PromotionPending);
+        }
+        if self.is_over() {
+            return Err(IllegalMove::GameOver);
+        }
+        if !check_bounds(to) {
+            return Err(IllegalMove::InvalidMovement);
+        }
+
+        let color = self.current_move;
+        let back_rank = to.y == 0 || to.y == 7;
+        if !self.variant.drops()
+            || piece_type == PieceType::King
+            || piece_type == PieceType::Pawn && back_rank
+        {
+            return Err(IllegalMove::InvalidDrop);
+        }
+        if self.pocket(color).count(piece_type) == 0 {
+            return Err(IllegalMove::NotInPocket);
+        }
+        if self.color_at(to).is_some() {
+            return Err(IllegalMove::PathBlocked);
+        }
+
+        // Anything left over is a drop that doesn't deal with a check
+        self.legal_moves()
+            .into_iter()
+            .find(|mov| {
+                mov.move_type == MoveType::Drop
+                    && mov.to == to
+                    && mov.piece.piece_type == piece_type
+            })
+            .ok_or(IllegalMove::LeavesKingInCheck)
+    }
+
+    /// Drops a piece from the pocket of the player to move, the drop version of `try_move`
+    pub fn drop_piece(&mut self, piece_type: PieceType, to: Position) -> Result<Move, IllegalMove> {
+        let mut mov = self.validate_drop(piece_type, to)?;
+        self.apply_move(mov);
+        self.press_clock(mov.piece.color);
+
+        mov.check = self.is_color_checked(self.current_move);
+        mov.checkmate = mov.check && !self.has_legal_moves(self.current_move);
+        Ok(mov)
+    }
+
+    /// Puts a dropped piece on the board, for `apply_move`
+    pub(crate) fn place_drop(&mut self, mov: Move) {
+        let piece = mov.piece;
+        self.pockets[piece.color as usize].take(piece.piece_type);
+        self.set_piece(mov.to, Some(piece));
+        self.toggle_zobrist(piece, piece.piece_type, mov.to);
+        self.en_passant_possible = None;
+    }
+
+    /// Puts a captured piece in the capturer's pocket when the variant has drops. Has to be
+    /// called before the promoted squares are updated for the capture
+    pub(crate) fn pocket_capture(&mut self, capturer: Color, captured: Piece) {
+        if !self.variant.drops() {
+            return;
+        }
+        let piece_type = match self.is_promoted(captured.position) {
+            true => PieceType::Pawn,
+            false => captured.piece_type,
+        };
+        self.pockets[capturer as usize].add(piece_type);
+    }
+}
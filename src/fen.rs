@@ -0,0 +1,518 @@
+use crate::{
+    bitboard::{self, Bitboard, Bitboards},
+    crazyhouse::{Pocket, DROPPABLE},
+    CastlingFiles, CastlingRights, CastlingSide, Color, Game, Piece, PieceType, Position,
+};
+
+use std::{fmt, str::FromStr};
+
+/// Reasons a FEN string can be rejected by `Game::load_fen`
+#[derive(Debug, Clone, PartialEq)]
+pub enum FenError {
+    /// The string has no piece placement field at all
+    Empty,
+    /// More than the six FEN fields
+    TooManyFields,
+    InvalidPiece(char),
+    /// A rank (counted from the top, starting at 0) does not cover exactly 8 files
+    InvalidRankLength(usize),
+    /// The piece placement does not contain exactly 8 ranks
+    InvalidRankCount(usize),
+    InvalidSideToMove(String),
+    InvalidCastling(String),
+    InvalidEnPassant(String),
+    InvalidHalfmoveClock(String),
+    InvalidFullmoveNumber(String),
+    /// The checks left for each player in Three-check, like `3+3`
+    InvalidCheckCount(String),
+    /// The pieces in the Crazyhouse pockets, like `[QNp]`
+    InvalidPocket(String),
+    MissingKing(Color),
+    TooManyKings(Color),
+    PawnOnBackRank(Position),
+    /// The side that is not to move is in check, which can't happen in a real game
+    OpponentInCheck(Color),
+}
+
+impl fmt::Display for FenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FenError::Empty => write!(f, "empty FEN string"),
+            FenError::TooManyFields => write!(f, "too many fields in FEN string"),
+            FenError::InvalidPiece(c) => write!(f, "invalid piece character '{}'", c),
+            FenError::InvalidRankLength(rank) => {
+                write!(f, "rank {} does not contain exactly 8 files", rank + 1)
+            }
+            FenError::InvalidRankCount(count) => write!(f, "expected 8 ranks, found {}", count),
+            FenError::InvalidSideToMove(side) => write!(f, "invalid side to move '{}'", side),
+            FenError::InvalidCastling(castling) => {
+                write!(f, "invalid castling rights '{}'", castling)
+            }
+            FenError::InvalidEnPassant(square) => {
+                write!(f, "invalid en passant square '{}'", square)
+            }
+            FenError::InvalidHalfmoveClock(clock) => {
+                write!(f, "invalid halfmove clock '{}'", clock)
+            }
+            FenError::InvalidFullmoveNumber(number) => {
+                write!(f, "invalid fullmove number '{}'", number)
+            }
+            FenError::InvalidCheckCount(checks) => write!(f, "invalid check count '{}'", checks),
+            FenError::InvalidPocket(pocket) => write!(f, "invalid pocket '{}'", pocket),
+            FenError::MissingKing(color) => write!(f, "{:?} has no king", color),
+            FenError::TooManyKings(color) => write!(f, "{:?} has more than one king", color),
+            FenError::PawnOnBackRank(position) => write!(f, "pawn on back rank at {}", position),
+            FenError::OpponentInCheck(color) => {
+                write!(f, "{:?} is in check but it is not their move", color)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FenError {}
+
+impl Game {
+    /// Loads a position from a FEN string, leaving the game untouched if the string is invalid.
+    /// Only the piece placement is required, missing trailing fields fall back to
+    /// white to move, castling inferred from the piece placement, no en passant and fresh clocks.
+    /// Castling can also be given as Shredder-FEN, which makes the game Chess960. `KQkq` has to
+    /// mean the usual squares here, `load_chess960_fen` also reads it as X-FEN.
+    /// Variants that count checks take the checks left as an extra field before the clocks,
+    /// and variants with drops take the pockets after the placement like `...RNBQKBNR[Qp]`,
+    /// or as a ninth rank
+    pub fn load_fen(&mut self, fen: &str) -> Result<(), FenError> {
+        self.load_fen_as(fen, false)
+    }
+
+    /// `load_fen`, with `chess960` saying whether the position is known to be Chess960
+    pub(crate) fn load_fen_as(&mut self, fen: &str, chess960: bool) -> Result<(), FenError> {
+        let mut fields = fen.split_whitespace().collect::<Vec<&str>>();
+        if fields.is_empty() {
+            return Err(FenError::Empty);
+        }
+
+        // Build the position on a fresh game so a bad string can't leave us half loaded
+        let mut game = Game::new();
+        game.variant = self.variant;
+
+        if let Some(limit) = game.variant.check_limit() {
+            if fields.get(4).is_some_and(|field| field.contains('+')) {
+                game.checks = parse_checks(fields.remove(4), limit)?;
+            }
+        }
+        if fields.len() > 6 {
+            return Err(FenError::TooManyFields);
+        }
+
+        let mut placement = fields[0];
+        if game.variant.drops() {
+            let pocket;
+            (placement, pocket) = split_pocket(placement);
+            game.pockets = parse_pockets(pocket)?;
+        }
+        (game.pieces, game.promoted) = parse_placement(placement)?;
+        game.bitboards = Bitboards::from_board(&game.pieces);
+
+        // Side to move
+        game.current_move = match fields.get(1) {
+            None | Some(&"w") => Color::White,
+            Some(&"b") => Color::Black,
+            Some(side) => return Err(FenError::InvalidSideToMove(side.to_string())),
+        };
+
+        game.validate_kings_and_pawns()?;
+
+        // Castling rights, inferred from the king and rook placement if left out
+        match fields.get(2) {
+            Some(castling) => {
+                (game.castling, game.castling_files) = game.parse_castling_rights(castling)?;
+                // Rook files mean Chess960, KQkq only stands for other squares in X-FEN
+                let rook_files = castling.chars().any(|c| !"KQkq-".contains(c));
+                if !chess960 && !rook_files && game.castling_files != CastlingFiles::default() {
+                    return Err(FenError::InvalidCastling(castling.to_string()));
+                }
+                game.chess960 = chess960 || rook_files;
+            }
+            None => {
+                game.castling = game.infer_castling_rights();
+                game.chess960 = chess960;
+            }
+        }
+
+        // En passant target square, the pawn that can be passed is one step past it
+        if let Some(target) = fields.get(3).filter(|target| **target != "-") {
+            game.en_passant_possible = Some(game.parse_en_passant(target)?);
+        }
+
+        // Halfmove clock and fullmove number
+        if let Some(clock) = fields.get(4) {
+            game.halfmove_clock = clock
+                .parse()
+                .map_err(|_| FenError::InvalidHalfmoveClock(clock.to_string()))?;
+        }
+        if let Some(number) = fields.get(5) {
+            game.fullmove_number = number
+                .parse()
+                .ok()
+                .filter(|number| *number > 0)
+                .ok_or_else(|| FenError::InvalidFullmoveNumber(number.to_string()))?;
+        }
+
+        if game.is_color_checked(!game.current_move) {
+            return Err(FenError::OpponentInCheck(!game.current_move));
+        }
+
+        game.zobrist = game.compute_zobrist();
+        *self = game;
+        Ok(())
+    }
+
+    /// Serializes the current position into a six-field FEN string
+    pub fn to_fen(&self) -> String {
+        let mut rows: Vec<String> = vec![];
+        for y in (0..8).rev() {
+            let mut row = String::new();
+            let mut empty = 0;
+            for x in 0..8 {
+                match self.pieces[x][y] {
+                    Some(piece) => {
+                        if empty > 0 {
+                            row.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        row.push(piece.to_char());
+                        if self.variant.drops() && self.is_promoted(piece.position) {
+                            row.push('~');
+                        }
+                    }
+                    None => empty += 1,
+                }
+            }
+            if empty > 0 {
+                row.push_str(&empty.to_string());
+            }
+            rows.push(row);
+        }
+
+        let side = match self.current_move {
+            Color::White => "w",
+            Color::Black => "b",
+        };
+
+        let en_passant = match self.en_passant_possible {
+            Some(pawn) => match pawn.color {
+                Color::White => (pawn.position - (0, 1)).to_string(),
+                Color::Black => (pawn.position + (0, 1)).to_string(),
+            },
+            None => "-".to_string(),
+        };
+
+        let checks = match self.variant.check_limit() {
+            Some(limit) => format!(
+                "{}+{} ",
+                limit.saturating_sub(self.checks[0]),
+                limit.saturating_sub(self.checks[1])
+            ),
+            None => String::new(),
+        };
+
+        let mut placement = rows.join("/");
+        if self.variant.drops() {
+            placement.push('[');
+            for color in [Color::White, Color::Black] {
+                let pocket = self.pocket(color);
+                for piece_type in pocket.piece_types() {
+                    let c = match color {
+                        Color::White => piece_type.to_char(),
+                        Color::Black => piece_type.to_char().to_ascii_lowercase(),
+                    };
+                    for _ in 0..pocket.count(piece_type) {
+                        placement.push(c);
+                    }
+                }
+            }
+            placement.push(']');
+        }
+
+        format!(
+            "{} {} {} {} {}{} {}",
+            placement,
+            side,
+            self.castling_rights_fen(),
+            en_passant,
+            checks,
+            self.halfmove_clock,
+            self.fullmove_number
+        )
+    }
+
+    /// `KQkq` style, or Shredder-FEN with the rook files for Chess960
+    fn castling_rights_fen(&self) -> String {
+        let mut castling = String::new();
+        for (color, short, long) in [(Color::White, 'K', 'Q'), (Color::Black, 'k', 'q')] {
+            for (side, letter) in [(CastlingSide::Short, short), (CastlingSide::Long, long)] {
+                if !self.castling.has(color, side) {
+                    continue;
+                }
+                castling.push(match self.chess960 {
+                    true => {
+                        let file = (b'a' + self.castling_files.rook(color, side) as u8) as char;
+                        match color {
+                            Color::White => file.to_ascii_uppercase(),
+                            Color::Black => file,
+                        }
+                    }
+                    false => letter,
+                });
+            }
+        }
+
+        if castling.is_empty() {
+            castling.push('-');
+        }
+        castling
+    }
+
+    fn validate_kings_and_pawns(&self) -> Result<(), FenError> {
+        for color in [Color::White, Color::Black] {
+            let kings = self
+                .get_pieces()
+                .iter()
+                .filter(|piece| piece.color == color && piece.piece_type == PieceType::King)
+                .count();
+
+            match kings {
+                0 => return Err(FenError::MissingKing(color)),
+                1 => (),
+                _ => return Err(FenError::TooManyKings(color)),
+            }
+        }
+
+        if let Some(pawn) = self.get_pieces().iter().find(|piece| {
+            piece.piece_type == PieceType::Pawn && (piece.position.y == 0 || piece.position.y == 7)
+        }) {
+            return Err(FenError::PawnOnBackRank(pawn.position));
+        }
+
+        Ok(())
+    }
+
+    /// Reads `KQkq`, Shredder-FEN rook files like `HAha`, or X-FEN which mixes the two.
+    /// `K` and `Q` mean the outermost rook on that side of the king
+    fn parse_castling_rights(
+        &self,
+        castling: &str,
+    ) -> Result<(CastlingRights, CastlingFiles), FenError> {
+        let invalid = || FenError::InvalidCastling(castling.to_string());
+        let mut rights = CastlingRights::default();
+        let mut files = CastlingFiles::default();
+
+        if castling == "-" {
+            return Ok((rights, files));
+        }
+
+        for c in castling.chars() {
+            let (color, y) = match c.is_ascii_uppercase() {
+                true => (Color::White, 0),
+                false => (Color::Black, 7),
+            };
+            let king = self
+                .king_position(color)
+                .filter(|king| king.y == y)
+                .ok_or_else(invalid)?;
+            let is_rook = |x: &i8| {
+                self.pieces[*x as usize][y as usize].is_some_and(|piece| {
+                    piece.color == color && piece.piece_type == PieceType::Rook
+                })
+            };
+
+            let (side, rook_x) = match c.to_ascii_lowercase() {
+                'k' => (CastlingSide::Short, (king.x + 1..8).rev().find(is_rook)),
+                'q' => (CastlingSide::Long, (0..king.x).find(is_rook)),
+                file @ 'a'..='h' => {
+                    let x = (file as u8 - b'a') as i8;
+                    let side = match x > king.x {
+                        true => CastlingSide::Short,
+                        false => CastlingSide::Long,
+                    };
+                    (side, Some(x).filter(is_rook))
+                }
+                _ => return Err(invalid()),
+            };
+
+            // Duplicates and rights without the king and rook at home make no sense
+            let rook_x = rook_x.ok_or_else(invalid)?;
+            if rights.has(color, side) {
+                return Err(invalid());
+            }
+
+            rights.set(color, side, true);
+            files.king[color as usize] = king.x;
+            files.rooks[color as usize][side as usize] = rook_x;
+        }
+
+        Ok((rights, files))
+    }
+
+    fn infer_castling_rights(&self) -> CastlingRights {
+        let mut rights = CastlingRights::default();
+        for color in [Color::White, Color::Black] {
+            for side in [CastlingSide::Short, CastlingSide::Long] {
+                rights.set(color, side, self.can_have_castling_right(color, side));
+            }
+        }
+        rights
+    }
+
+    /// Whether the king and the rook for a castling move are on their starting squares
+    fn can_have_castling_right(&self, color: Color, side: CastlingSide) -> bool {
+        let y = match color {
+            Color::White => 0,
+            Color::Black => 7,
+        };
+        let rook_x = match side {
+            CastlingSide::Short => 7,
+            CastlingSide::Long => 0,
+        };
+
+        let at_home = |x: usize, piece_type: PieceType| {
+            self.pieces[x][y]
+                .is_some_and(|piece| piece.color == color && piece.piece_type == piece_type)
+        };
+        at_home(4, PieceType::King) && at_home(rook_x, PieceType::Rook)
+    }
+
+    /// Finds the pawn that just double stepped past the given en passant target square
+    fn parse_en_passant(&self, target: &str) -> Result<Piece, FenError> {
+        let invalid = || FenError::InvalidEnPassant(target.to_string());
+        let target = Position::from_str(target).map_err(|_| invalid())?;
+
+        // The target square is behind a pawn of the side that just moved
+        let (target_rank, direction) = match self.current_move {
+            Color::White => (5, -1),
+            Color::Black => (2, 1),
+        };
+        if target.y != target_rank {
+            return Err(invalid());
+        }
+
+        let pawn_position = target + (0, direction);
+        let origin = target - (0, direction);
+        let pawn = self.pieces[pawn_position.x as usize][pawn_position.y as usize]
+            .filter(|piece| piece.piece_type == PieceType::Pawn && piece.color != self.current_move)
+            .ok_or_else(invalid)?;
+
+        if self.color_at(target).is_some() || self.color_at(origin).is_some() {
+            return Err(invalid());
+        }
+
+        Ok(pawn)
+    }
+}
+
+/// Checks left like `3+3` for white and black, turned into checks given
+fn parse_checks(field: &str, limit: u8) -> Result<[u8; 2], FenError> {
+    let invalid = || FenError::InvalidCheckCount(field.to_string());
+    let (white, black) = field.split_once('+').ok_or_else(invalid)?;
+    let given = |left: &str| {
+        left.parse::<u8>()
+            .ok()
+            .filter(|left| *left <= limit)
+            .map(|left| limit - left)
+            .ok_or_else(invalid)
+    };
+    Ok([given(white)?, given(black)?])
+}
+
+/// Splits the pockets off the placement, they either come in brackets at the end or
+/// as a ninth rank
+fn split_pocket(placement: &str) -> (&str, &str) {
+    if let Some(board) = placement.strip_suffix(']') {
+        if let Some((board, pocket)) = board.split_once('[') {
+            return (board, pocket);
+        }
+    }
+    match placement.match_indices('/').nth(7) {
+        Some((index, _)) => (&placement[..index], &placement[index + 1..]),
+        None => (placement, ""),
+    }
+}
+
+/// Pieces in the pockets, uppercase for white and lowercase for black in any order.
+/// More of a piece than there is in a game is rejected
+fn parse_pockets(pocket: &str) -> Result<[Pocket; 2], FenError> {
+    let invalid = || FenError::InvalidPocket(pocket.to_string());
+    let mut pockets = [Pocket::default(); 2];
+    for c in pocket.chars() {
+        let piece_type = PieceType::from_char(c)
+            .filter(|piece_type| DROPPABLE.contains(piece_type))
+            .ok_or_else(invalid)?;
+        let color = match c.is_ascii_lowercase() {
+            true => Color::Black,
+            false => Color::White,
+        };
+        let pocket = &mut pockets[color as usize];
+        if !pocket.add(piece_type) || pocket.count(piece_type) > Pocket::max(piece_type) {
+            return Err(invalid());
+        }
+    }
+    Ok(pockets)
+}
+
+/// The pieces, and the squares of pieces marked as promoted with a `~` after them
+fn parse_placement(placement: &str) -> Result<(crate::Board, Bitboard), FenError> {
+    let mut pieces: crate::Board = [[None; 8]; 8];
+    let mut promoted = 0;
+
+    let rows = placement.split("/").collect::<Vec<&str>>();
+    if rows.len() != 8 {
+        return Err(FenError::InvalidRankCount(rows.len()));
+    }
+
+    for (rank, row) in rows.iter().enumerate() {
+        let y = 7 - rank as i8;
+        let mut x = 0;
+        let mut last_piece = None;
+        for c in row.chars() {
+            if c == '~' {
+                let position: Position = last_piece.take().ok_or(FenError::InvalidPiece(c))?;
+                promoted |= bitboard::bit(position);
+                continue;
+            }
+            last_piece = None;
+
+            if x >= 8 {
+                return Err(FenError::InvalidRankLength(rank));
+            }
+
+            if let Some(empty) = c.to_digit(10) {
+                if !(1..=8).contains(&empty) {
+                    return Err(FenError::InvalidPiece(c));
+                }
+                x += empty as i8;
+                continue;
+            }
+
+            let piece = Piece {
+                color: match c.is_ascii_lowercase() {
+                    true => Color::Black,
+                    false => Color::White,
+                },
+                piece_type: PieceType::from_char(c).ok_or(FenError::InvalidPiece(c))?,
+                position: Position { x, y },
+                num_moves: 0,
+            };
+
+            pieces[x as usize][y as usize] = Some(piece);
+            last_piece = Some(piece.position);
+
+            x += 1;
+        }
+
+        if x != 8 {
+            return Err(FenError::InvalidRankLength(rank));
+        }
+    }
+
+    Ok((pieces, promoted))
+}
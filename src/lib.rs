@@ -1,19 +1,79 @@
 use std::{ops, str::FromStr};
 
-use moves::{check_bounds, get_move_type, get_pseudo_moves, MoveType, Moves};
-
+use bitboard::{Bitboard, Bitboards};
+use clock::{Clock, Turn};
+use crazyhouse::Pocket;
+use moves::{
+    check_bounds, get_move_type, is_promotion, IllegalMove, Move, MoveList, MoveType, Moves,
+};
+use termination::{GameResult, Reason};
+use variant::{Standard, Variant};
+
+pub mod bitboard;
+pub mod chess960;
+pub mod clock;
+pub mod crazyhouse;
+pub mod fen;
+pub mod movegen;
 pub mod moves;
+pub mod notation;
+pub mod perft;
+pub mod pgn;
+pub mod termination;
 pub mod tests;
+pub mod variant;
+pub mod zobrist;
 
 pub type Board = [[Option<Piece>; 8]; 8];
 
-#[derive(Debug, Default, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub struct Game {
     pub pieces: Board,
     pub current_move: Color,
     status: GameStatus,
     en_passant_possible: Option<Piece>,
-    moves_since_capture: u32,
+    halfmove_clock: u32,
+    fullmove_number: u32,
+    castling: CastlingRights,
+    /// Where the king and rooks castle from, only unusual in Chess960
+    castling_files: CastlingFiles,
+    chess960: bool,
+    history: Vec<HistoryEntry>,
+    /// Zobrist key of the current position, see `zobrist_key`
+    zobrist: u64,
+    /// Same pieces as `pieces`, used for move generation
+    bitboards: Bitboards,
+    /// Set once the game has ended, by the board or by the players
+    result: Option<GameResult>,
+    draw_offer: Option<Color>,
+    clock: Option<Clock>,
+    variant: &'static dyn Variant,
+    /// Checks given by each color, for variants that count them
+    checks: [u8; 2],
+    /// Captured pieces waiting to be dropped, for variants with drops
+    pockets: [Pocket; 2],
+    /// Squares with pieces that got there by promoting
+    promoted: Bitboard,
+}
+
+/// A played move together with the state it overwrote, so it can be taken back
+#[derive(Debug, Clone)]
+struct HistoryEntry {
+    mov: Move,
+    /// Zobrist key of the position before the move, also used for repetitions
+    zobrist: u64,
+    status: GameStatus,
+    en_passant_possible: Option<Piece>,
+    halfmove_clock: u32,
+    fullmove_number: u32,
+    castling: CastlingRights,
+    result: Option<GameResult>,
+    draw_offer: Option<Color>,
+    checks: [u8; 2],
+    pockets: [Pocket; 2],
+    promoted: Bitboard,
+    /// The mover's clock before the move, if the move pressed one
+    clock: Option<Turn>,
 }
 
 #[derive(Debug, Default, Clone, Copy, PartialEq)]
@@ -24,22 +84,68 @@ pub enum GameStatus {
     Checkmate(Color),
     Stalemate,
     Promotion(Piece),
+    /// 50 moves by each player without a capture or pawn move, either player may claim a draw
     FiftyMoveRule,
+    /// 75 moves by each player without a capture or pawn move, the game is drawn
+    SeventyFiveMoveRule,
+    /// Neither player has enough pieces left to ever checkmate
+    InsufficientMaterial,
+    /// Only kings and pawns that are locked in place, so no checkmate can ever happen
+    DeadPosition,
+    /// The position has occurred three times, either player may claim a draw
+    ThreefoldRepetition,
+    /// The position has occurred five times, the game is drawn
+    FivefoldRepetition,
+    /// The rules of the variant being played ended the game, see `Game::result`
+    VariantEnd,
+}
+
+impl GameStatus {
+    /// Whether the game has ended, claimable draws don't count until they are claimed
+    pub fn is_game_over(&self) -> bool {
+        matches!(
+            self,
+            GameStatus::Checkmate(_)
+                | GameStatus::Stalemate
+                | GameStatus::SeventyFiveMoveRule
+                | GameStatus::InsufficientMaterial
+                | GameStatus::DeadPosition
+                | GameStatus::FivefoldRepetition
+                | GameStatus::VariantEnd
+        )
+    }
 }
 
 impl Game {
     pub fn new() -> Self {
-        Self {
+        let mut game = Self {
             pieces: [[None; 8]; 8],
             current_move: Color::White,
             status: GameStatus::Active,
             en_passant_possible: None,
-            moves_since_capture: 0,
-        }
+            halfmove_clock: 0,
+            fullmove_number: 1,
+            castling: CastlingRights::default(),
+            castling_files: CastlingFiles::default(),
+            chess960: false,
+            history: Vec::new(),
+            zobrist: 0,
+            bitboards: Bitboards::default(),
+            result: None,
+            draw_offer: None,
+            clock: None,
+            variant: &Standard,
+            checks: [0; 2],
+            pockets: [Pocket::default(); 2],
+            promoted: 0,
+        };
+        game.zobrist = game.compute_zobrist();
+        game
     }
 
+    /// Sets up the starting position of the variant being played
     pub fn default_board(&mut self) {
-        self.load_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR");
+        self.load_fen(self.variant.starting_fen()).unwrap();
     }
 
     pub fn clear_board(&mut self) {
@@ -48,77 +154,37 @@ impl Game {
                 self.pieces[x][y] = None;
             }
         }
+        self.bitboards = Bitboards::default();
+        self.en_passant_possible = None;
+        self.zobrist = self.compute_zobrist();
     }
 
-    // TODO: More checking of FEN string to assure valid board
-    pub fn load_fen(&mut self, fen: &str) {
-        let fields = fen.split(" ").collect::<Vec<&str>>();
-
-        // Parse piece placements
-        let mut rows = fields[0].split("/").collect::<Vec<&str>>();
-        rows.reverse();
-
-        self.clear_board();
-
-        let mut y = 0;
-        for row in rows {
-            let mut x = 0;
-            for c in row.chars() {
-                let mut piece = Piece {
-                    color: Color::White,
-                    piece_type: PieceType::Pawn,
-                    position: Position { x, y },
-                    num_moves: 0,
-                };
-
-                if c.is_ascii_lowercase() {
-                    // Lowercase letter
-                    piece.color = Color::Black;
-                }
-
-                if c.is_ascii_digit() {
-                    // Digit
-                    x += c.to_digit(10).unwrap() as i8; // Should never fail so why not unwrap :)
-                    continue;
-                }
+    pub fn castling_rights(&self) -> CastlingRights {
+        self.castling
+    }
 
-                match c.to_ascii_lowercase() {
-                    'p' => piece.piece_type = PieceType::Pawn,
-                    'n' => piece.piece_type = PieceType::Knight,
-                    'b' => piece.piece_type = PieceType::Bishop,
-                    'r' => piece.piece_type = PieceType::Rook,
-                    'q' => piece.piece_type = PieceType::Queen,
-                    'k' => piece.piece_type = PieceType::King,
-                    _ => panic!("Bruh"),
-                }
+    pub fn castling_files(&self) -> CastlingFiles {
+        self.castling_files
+    }
 
-                self.pieces[x as usize][y as usize] = Some(piece);
+    /// Whether castling follows the Chess960 rules, which is decided when the position is
+    /// loaded. Castling moves are written as the king taking its own rook then
+    pub fn is_chess960(&self) -> bool {
+        self.chess960
+    }
 
-                x += 1;
-            }
-            y += 1
-        }
+    pub fn bitboards(&self) -> &Bitboards {
+        &self.bitboards
     }
 
     pub fn print_board(&self) {
         for y in (0..8).rev() {
             for x in 0..8 {
-                let mut c = match self.pieces[x][y] {
+                let c = match self.pieces[x][y] {
                     None => '.',
-                    Some(piece) => match piece.piece_type {
-                        PieceType::King => 'K',
-                        PieceType::Queen => 'Q',
-                        PieceType::Bishop => 'B',
-                        PieceType::Knight => 'N',
-                        PieceType::Rook => 'R',
-                        PieceType::Pawn => 'P',
-                    },
+                    Some(piece) => piece.to_char(),
                 };
 
-                if self.pieces[x][y].is_some_and(|piece| piece.color == Color::Black) {
-                    c = c.to_ascii_lowercase();
-                }
-
                 print!("{} ", c);
             }
             println!();
@@ -136,8 +202,22 @@ impl Game {
         };
 
         piece.position = to;
-        self.pieces[from.x as usize][from.y as usize] = None;
-        self.pieces[to.x as usize][to.y as usize] = Some(piece);
+        self.set_piece(from, None);
+        self.set_piece(to, Some(piece));
+    }
+
+    /// Puts a piece on a square, or empties it. All board changes go through here so the
+    /// bitboards stay in sync
+    fn set_piece(&mut self, position: Position, piece: Option<Piece>) {
+        let square = &mut self.pieces[position.x as usize][position.y as usize];
+        let index = bitboard::square(position);
+        if let Some(old) = *square {
+            self.bitboards.toggle(old.color, old.piece_type, index);
+        }
+        if let Some(new) = piece {
+            self.bitboards.toggle(new.color, new.piece_type, index);
+        }
+        *square = piece;
     }
 
     pub fn get_pieces(&self) -> Vec<Piece> {
@@ -153,44 +233,37 @@ impl Game {
     }
 
     pub fn is_color_checked(&self, color: Color) -> bool {
-        let pieces = self.get_pieces();
-        let king = *pieces
-            .iter()
-            .find(|piece| piece.color == color && piece.piece_type == PieceType::King)
-            .unwrap();
-
-        for piece in pieces {
-            if piece.color != color
-                && get_pseudo_moves(self, piece)
-                    .iter()
-                    .any(|position| *position == king.position)
-            {
-                return true;
-            }
-        }
-
-        false
+        let king = self.king_position(color).unwrap();
+        self.is_square_attacked(king, !color)
     }
 
-    fn is_color_checked_exclude_king(&self, color: Color) -> bool {
-        let pieces = self.get_pieces();
-        let king = *pieces
-            .iter()
-            .find(|piece| piece.color == color && piece.piece_type == PieceType::King)
-            .unwrap();
+    fn king_position(&self, color: Color) -> Option<Position> {
+        bitboard::squares(self.bitboards.pieces(color, PieceType::King))
+            .next()
+            .map(bitboard::position)
+    }
 
-        for piece in pieces {
-            if piece.color != color
-                && piece.piece_type != PieceType::King
-                && get_pseudo_moves(self, piece)
-                    .iter()
-                    .any(|position| *position == king.position)
-            {
-                return true;
-            }
-        }
+    /// Whether any piece of a color attacks a square, no matter what is standing on it.
+    /// Looks outwards from the square instead of generating the moves of every piece
+    pub fn is_square_attacked(&self, square: Position, by_color: Color) -> bool {
+        self.bitboards.is_attacked(
+            bitboard::square(square),
+            by_color,
+            self.bitboards.occupied(),
+        )
+    }
 
-        false
+    /// Every piece of either color attacking a square
+    pub fn attackers_of(&self, square: Position) -> Vec<Piece> {
+        let attackers = self
+            .bitboards
+            .attackers(bitboard::square(square), self.bitboards.occupied());
+        bitboard::squares(attackers)
+            .map(|square| {
+                let position = bitboard::position(square);
+                self.pieces[position.x as usize][position.y as usize].unwrap()
+            })
+            .collect()
     }
 
     /// Returns the color of the currently checked player, None if no player is checked
@@ -211,43 +284,228 @@ impl Game {
         None
     }
 
+    /// Moves a piece, promotions are left pending until `promote` is called
     pub fn move_piece(&mut self, from: Position, to: Position) -> MoveType {
-        let piece = match self.pieces[from.x as usize][from.y as usize] {
-            Some(p) => p,
-            None => return MoveType::Invalid,
+        match self.validate_movement(from, to) {
+            Ok(mov) => {
+                self.apply_move(mov);
+                self.press_clock(mov.piece.color);
+                mov.move_type
+            }
+            Err(_) => MoveType::Invalid,
+        }
+    }
+
+    /// Plays a move in one step, including the promotion if there is one.
+    /// Only `from`, `to` and `promotion` of the move are looked at, so moves from
+    /// `get_moves` and hand written ones work the same. Drops also need their piece type
+    pub fn make_move(&mut self, mov: Move) -> MoveType {
+        let played = match mov.move_type {
+            MoveType::Drop => self.drop_piece(mov.piece.piece_type, mov.to),
+            _ => self.try_move(mov.from, mov.to, mov.promotion),
+        };
+        played.map_or(MoveType::Invalid, |mov| mov.move_type)
+    }
+
+    /// Plays a move in one step like `make_move`, but says why the move is illegal if it is.
+    /// The returned move has its check flags filled in
+    pub fn try_move(
+        &mut self,
+        from: Position,
+        to: Position,
+        promotion: Option<PieceType>,
+    ) -> Result<Move, IllegalMove> {
+        let mut mov = self.validate_move(from, to, promotion)?;
+        self.apply_move(mov);
+        self.press_clock(mov.piece.color);
+
+        mov.check = self.is_color_checked(self.current_move);
+        mov.checkmate = mov.check && !self.has_legal_moves(self.current_move);
+        Ok(mov)
+    }
+
+    /// Checks a move without playing it, the promotion has to be given when the move promotes.
+    /// The returned move has no check flags
+    pub fn validate_move(
+        &self,
+        from: Position,
+        to: Position,
+        promotion: Option<PieceType>,
+    ) -> Result<Move, IllegalMove> {
+        let mov = self.validate_movement(from, to)?;
+
+        let promotion_valid = match promotion {
+            Some(PieceType::King) | Some(PieceType::Pawn) => false,
+            Some(_) => is_promotion(mov.piece, to),
+            None => !is_promotion(mov.piece, to),
         };
+        if !promotion_valid {
+            return Err(IllegalMove::InvalidPromotion);
+        }
+
+        Ok(Move { promotion, ..mov })
+    }
+
+    /// Everything about a move except the promotion
+    fn validate_movement(&self, from: Position, to: Position) -> Result<Move, IllegalMove> {
+        if let GameStatus::Promotion(_) = self.status {
+            return Err(IllegalMove::PromotionPending);
+        }
+        if self.is_over() {
+            return Err(IllegalMove::GameOver);
+        }
 
+        let piece = match check_bounds(from) {
+            true => self.pieces[from.x as usize][from.y as usize].ok_or(IllegalMove::NoPiece)?,
+            false => return Err(IllegalMove::NoPiece),
+        };
         if piece.color != self.current_move {
-            return MoveType::Invalid;
+            return Err(IllegalMove::WrongSide);
         }
 
-        let possible_moves = self.get_valid_moves(piece);
+        let to = self.castling_input(piece, to);
+        if !self.get_valid_moves(piece).contains(&to) {
+            return Err(self.illegal_reason(piece, to));
+        }
+
+        Ok(self.build_move(piece, to, None))
+    }
 
-        if !possible_moves.contains(&to) {
-            return MoveType::Invalid;
+    /// Piece of the player to move at a position, if there is one
+    fn movable_piece(&self, position: Position) -> Option<Piece> {
+        if !check_bounds(position) || self.status_blocks_moves() {
+            return None;
         }
+
+        self.pieces[position.x as usize][position.y as usize]
+            .filter(|piece| piece.color == self.current_move)
+    }
+
+    fn status_blocks_moves(&self) -> bool {
+        matches!(self.status, GameStatus::Promotion(_)) || self.is_over()
+    }
+
+    /// Fills in everything about a move except the check flags
+    fn build_move(&self, piece: Piece, to: Position, promotion: Option<PieceType>) -> Move {
         let move_type = get_move_type(self, piece, to);
+        let captured = match move_type {
+            MoveType::Attack => self.pieces[to.x as usize][to.y as usize],
+            MoveType::EnPassant => self.en_passant_possible,
+            _ => None,
+        };
+
+        Move {
+            from: piece.position,
+            to,
+            piece,
+            captured,
+            promotion,
+            move_type,
+            check: false,
+            checkmate: false,
+        }
+    }
+
+    /// Applies an already validated move to the board
+    fn apply_move(&mut self, mov: Move) {
+        let piece = mov.piece;
+
+        self.history.push(HistoryEntry {
+            mov,
+            zobrist: self.zobrist,
+            status: self.status,
+            en_passant_possible: self.en_passant_possible,
+            halfmove_clock: self.halfmove_clock,
+            fullmove_number: self.fullmove_number,
+            castling: self.castling,
+            result: self.result,
+            draw_offer: self.draw_offer,
+            checks: self.checks,
+            pockets: self.pockets,
+            promoted: self.promoted,
+            clock: None,
+        });
+
+        // Side, castling, en passant and pockets are swapped in again once the move is done
+        self.zobrist ^= self.state_key();
+        match mov.move_type {
+            MoveType::Drop => self.place_drop(mov),
+            _ => self.move_on_board(mov),
+        }
+
+        // 50 and 75 move rules count plies since the last capture or pawn move
+        if mov.captured.is_some() || piece.piece_type == PieceType::Pawn {
+            self.halfmove_clock = 0;
+        } else {
+            // Huge counters can come in through FEN, they just stop counting
+            self.halfmove_clock = self.halfmove_clock.saturating_add(1);
+        }
+
+        let variant = self.variant;
+        variant.after_move(self, &mov);
+
+        // Moving instead of answering a draw offer declines it
+        if self.draw_offer == Some(!self.current_move) {
+            self.draw_offer = None;
+        }
+
+        if self.current_move == Color::Black {
+            self.fullmove_number = self.fullmove_number.saturating_add(1);
+        }
+        self.current_move = !self.current_move;
+        self.zobrist ^= self.state_key();
+    }
+
+    /// The board part of `apply_move` for a piece that is already on the board
+    fn move_on_board(&mut self, mov: Move) {
+        let piece = mov.piece;
+        let to = mov.to;
+        let move_type = mov.move_type;
+
+        // Castling in Chess960 is written as taking the rook, the king still ends up on c or g
+        let castling = mov.castling_side().map(|side| {
+            let (king_to, rook_from, _) = self.castling_squares(piece.color, side);
+            (side, king_to, rook_from)
+        });
+        let to = castling.map_or(to, |(_, king_to, _)| king_to);
+
+        self.toggle_zobrist(piece, piece.piece_type, piece.position);
+        self.toggle_zobrist(piece, mov.promotion.unwrap_or(piece.piece_type), to);
+        if let Some(captured) = mov.captured {
+            self.toggle_zobrist(captured, captured.piece_type, captured.position);
+            self.pocket_capture(piece.color, captured);
+            self.promoted &= !bitboard::bit(captured.position);
+        }
+
+        // Promoted pieces stay promoted wherever they go
+        let was_promoted = self.is_promoted(piece.position);
+        self.promoted &= !bitboard::bit(piece.position);
+        if was_promoted || is_promotion(piece, to) {
+            self.promoted |= bitboard::bit(to);
+        }
 
         // Reset en passant thingy
-        let saved_en_passant = self.en_passant_possible; // please end my suffering
         self.en_passant_possible = None;
 
+        // The rook gets out of the way first, the king can land on its square in Chess960
+        let castling_rook = castling.map(|(_, _, rook_from)| {
+            let rook = self.pieces[rook_from.x as usize][rook_from.y as usize].unwrap();
+            self.set_piece(rook_from, None);
+            rook
+        });
+
         self.force_move(piece.position, to);
 
         // Increase move counter
-        self.pieces[to.x as usize][to.y as usize]
-            .as_mut()
-            .unwrap()
-            .num_moves += 1;
-
-        // Copy updated piece for later use
-        let new_piece = self.pieces[to.x as usize][to.y as usize].unwrap();
+        let mut new_piece = self.pieces[to.x as usize][to.y as usize].unwrap();
+        new_piece.num_moves += 1;
+        if let Some(promotion) = mov.promotion {
+            new_piece.piece_type = promotion;
+        }
+        self.set_piece(to, Some(new_piece));
 
-        if piece.piece_type == PieceType::Pawn
-            && ((to.y == 0 && piece.color == Color::Black)
-                || (to.y == 7 && piece.color == Color::White))
-        {
-            // Pawn promotion
+        if mov.promotion.is_none() && is_promotion(piece, to) {
+            // Pawn promotion, the player still has to pick a piece
             self.status = GameStatus::Promotion(new_piece);
         }
 
@@ -255,106 +513,203 @@ impl Game {
         if piece.piece_type == PieceType::Pawn && (piece.position - to).y.abs() == 2 {
             self.en_passant_possible = Some(new_piece);
         }
-        // Capture if en passant happened or something
 
         // Capture if en passant happened or something idk at this point
         if move_type == MoveType::EnPassant {
-            let passed = saved_en_passant.unwrap(); // Should not fail :sunglasses:
-            self.pieces[passed.position.x as usize][passed.position.y as usize] = None;
+            let passed = mov.captured.unwrap(); // Should not fail :sunglasses:
+            self.set_piece(passed.position, None);
             // goodbye bozo
         }
 
-        // 50 move rule
-        if get_move_type(self, piece, to) == MoveType::Attack {
-            self.moves_since_capture = 0;
-        } else {
-            self.moves_since_capture += 1;
+        // Castling, put the rook who's nuts just got played with back down
+        if let (Some((side, _, _)), Some(rook)) = (castling, castling_rook) {
+            let rook_to = (side.rook_file(), piece.position.y).into();
+            self.set_piece(
+                rook_to,
+                Some(Piece {
+                    position: rook_to,
+                    ..rook
+                }),
+            );
+            self.toggle_zobrist(rook, PieceType::Rook, rook.position);
+            self.toggle_zobrist(rook, PieceType::Rook, rook_to);
         }
 
-        // Castling
-        if move_type == MoveType::Castling {
-            // Find rook who's nuts just got played with
-            // x is the target position for the rook
-            let (rook, x) = match new_piece.position.x {
-                2 => (self.pieces[0][new_piece.position.y as usize].unwrap(), 3),
-                6 => (self.pieces[7][new_piece.position.y as usize].unwrap(), 5),
-                _ => panic!("Castling has gone very wrong :("),
-            };
+        // Moving the king or a rook, or capturing a rook at home loses castling rights
+        self.castling.update(&self.castling_files, mov.from, mov.to);
+    }
 
-            // Move it
-            self.force_move(rook.position, (x, piece.position.y).into());
+    /// Adds or removes a piece of some type on a square from the Zobrist key
+    fn toggle_zobrist(&mut self, piece: Piece, piece_type: PieceType, position: Position) {
+        self.zobrist ^= zobrist::piece_key(piece_type, piece.color, position);
+    }
+
+    /// Takes back the last move, restoring the board and all game state to how it was before it.
+    /// Returns the move that was taken back, or None if no moves have been played
+    pub fn unmake_move(&mut self) -> Option<Move> {
+        let entry = self.history.pop()?;
+        let mov = entry.mov;
+
+        match mov.castling_side() {
+            _ if mov.move_type == MoveType::Drop => self.set_piece(mov.to, None),
+            Some(side) => {
+                // Both come off before either goes back, they can swap squares in Chess960
+                let (king_to, rook_from, rook_to) = self.castling_squares(mov.piece.color, side);
+                let rook = self.pieces[rook_to.x as usize][rook_to.y as usize].unwrap();
+                self.set_piece(king_to, None);
+                self.set_piece(rook_to, None);
+                self.set_piece(
+                    rook_from,
+                    Some(Piece {
+                        position: rook_from,
+                        ..rook
+                    }),
+                );
+                self.set_piece(mov.from, Some(mov.piece));
+            }
+            None => {
+                self.set_piece(mov.to, None);
+                self.set_piece(mov.from, Some(mov.piece));
+                if let Some(captured) = mov.captured {
+                    // Not always on `to` because of en passant
+                    self.set_piece(captured.position, Some(captured));
+                }
+            }
         }
 
-        self.current_move = !self.current_move;
+        self.current_move = mov.piece.color;
+        self.status = entry.status;
+        self.en_passant_possible = entry.en_passant_possible;
+        self.halfmove_clock = entry.halfmove_clock;
+        self.fullmove_number = entry.fullmove_number;
+        self.castling = entry.castling;
+        self.zobrist = entry.zobrist;
+        self.result = entry.result;
+        self.draw_offer = entry.draw_offer;
+        self.checks = entry.checks;
+        self.pockets = entry.pockets;
+        self.promoted = entry.promoted;
+
+        Some(mov)
+    }
 
-        move_type
+    /// Same as `unmake_move`, for takebacks. The clock is taken back too, the player gets
+    /// the turn back without the increment the move earned. A game the players ended
+    /// themselves by resigning or agreeing to a draw stays over and nothing is undone
+    pub fn undo(&mut self) -> Option<Move> {
+        if self
+            .result
+            .is_some_and(|result| matches!(result.reason, Reason::Resignation | Reason::Agreement))
+        {
+            return None;
+        }
+        let turn = self.history.last()?.clock;
+        let mov = self.unmake_move()?;
+        if let (Some(clock), Some(turn)) = (&mut self.clock, turn) {
+            clock.take_back(mov.piece.color, turn);
+        }
+        Some(mov)
     }
 
-    /// Checks whether or not a move puts own player in check
-    // Creates a copy of the board instead of mutating it (to avoid shit going horribly wrong)
-    fn self_check(&self, piece: Piece, position: Position) -> bool {
-        let mut copy_game = *self;
-        copy_game.force_move(piece.position, position); // Move piece to new position to see if checked
+    /// Moves played since the position was set up, oldest first
+    pub fn history(&self) -> Vec<Move> {
+        self.history.iter().map(|entry| entry.mov).collect()
+    }
 
-        if copy_game.is_color_checked(piece.color) {
-            return true;
-        }
+    /// How many times the current position has occurred since the game was set up,
+    /// counting the current occurrence
+    pub fn repetition_count(&self) -> usize {
+        1 + self
+            .history
+            .iter()
+            .filter(|entry| entry.zobrist == self.zobrist)
+            .count()
+    }
 
-        false
+    /// Copy of the game without the move history or clock, for trying out moves
+    fn scratch_copy(&self) -> Game {
+        Game {
+            history: Vec::new(),
+            clock: None,
+            ..*self
+        }
     }
 
     /// Performs full validation of valid moves, including blocking checked moves etc
     pub fn get_valid_moves(&self, piece: Piece) -> Moves {
-        let moves = get_pseudo_moves(self, piece);
+        let mut moves = MoveList::new();
+        self.generate_moves_from(piece.position, &mut moves);
 
-        // Filter out moves that puts own player in check
-        let valid_moves: Vec<Position> = moves
+        // Promotions come once per piece choice, only the square matters here
+        moves
             .iter()
-            .filter(|mov| !self.self_check(piece, **mov))
-            .cloned()
-            .collect();
+            .filter(|mov| {
+                mov.promotion
+                    .is_none_or(|promotion| promotion == PieceType::Queen)
+            })
+            .map(|mov| mov.to)
+            .collect()
+    }
 
-        valid_moves
+    /// All legal moves for a piece with full move information, one move per promotion choice
+    pub fn get_moves(&self, piece: Piece) -> Vec<Move> {
+        let mut moves = MoveList::new();
+        self.generate_moves_from(piece.position, &mut moves);
+        moves
+            .into_iter()
+            .map(|mov| self.with_check_flags(mov))
+            .collect()
     }
 
-    /// Checks if a certain moves the player out of a checked position
-    fn escape_check(&self, piece: Piece, position: Position) -> bool {
-        let mut copy_game = *self;
-        copy_game.force_move(piece.position, position); // Move piece to new position to see if checked
+    /// Every legal move for the player to move, with one move per promotion choice.
+    /// The check flags are left unset since working them out means playing every move,
+    /// `get_moves` fills them in
+    pub fn legal_moves(&self) -> MoveList {
+        let mut moves = MoveList::new();
+        if !self.status_blocks_moves() {
+            self.generate_moves(self.current_move, &mut moves);
+        }
+        moves
+    }
 
-        if copy_game.is_check().is_none()
-            || copy_game
-                .is_check()
-                .is_some_and(|color| color != piece.color)
-        {
-            return true;
+    /// Legal moves of the piece on a square, empty if it isn't the player to move's piece
+    pub fn legal_moves_from(&self, square: Position) -> MoveList {
+        let mut moves = MoveList::new();
+        if self.movable_piece(square).is_some() {
+            self.generate_moves_from(square, &mut moves);
         }
+        moves
+    }
+
+    /// Fills in whether a legal move checks or mates the opponent
+    pub(crate) fn with_check_flags(&self, mut mov: Move) -> Move {
+        // Play it on a copy to see what it does to the opponent
+        let mut copy_game = self.scratch_copy();
+        copy_game.apply_move(mov);
+        mov.check = copy_game.is_color_checked(!mov.piece.color);
+        mov.checkmate = mov.check && !copy_game.has_legal_moves(!mov.piece.color);
+        mov
+    }
 
-        false
+    /// Whether a legal move puts the opponent in check
+    pub(crate) fn gives_check(&self, mov: Move) -> bool {
+        let mut copy_game = self.scratch_copy();
+        copy_game.apply_move(mov);
+        copy_game.is_color_checked(!mov.piece.color)
+    }
+
+    fn has_legal_moves(&self, color: Color) -> bool {
+        let mut moves = MoveList::new();
+        self.generate_moves(color, &mut moves);
+        !moves.is_empty()
     }
 
     pub fn is_checkmate(&self) -> Option<Color> {
         // Color that is checked
-        let checked = match self.is_check() {
-            Some(color) => color,
-            None => return None,
-        };
+        let checked = self.is_check()?;
 
-        // All pieces of that color
-        let pieces: Vec<Piece> = self
-            .get_pieces()
-            .iter()
-            .filter(|piece| piece.color == checked)
-            .cloned()
-            .collect();
-
-        for piece in pieces {
-            let moves = self.get_valid_moves(piece);
-            for mov in moves {
-                if self.escape_check(piece, mov) {
-                    return None;
-                }
-            }
+        if self.has_legal_moves(checked) {
+            return None;
         }
 
         Some(checked)
@@ -365,47 +720,207 @@ impl Game {
             return false;
         }
 
+        !self.has_legal_moves(self.current_move)
+    }
+
+    /// K vs K, K+B vs K, K+N vs K, and any number of bishops that all stand on the same color
+    pub fn is_insufficient_material(&self) -> bool {
         let pieces: Vec<Piece> = self
             .get_pieces()
-            .iter()
-            .filter(|piece| piece.color == self.current_move)
-            .cloned()
+            .into_iter()
+            .filter(|piece| piece.piece_type != PieceType::King)
             .collect();
 
-        for piece in pieces {
-            if !self.get_valid_moves(piece).is_empty() {
+        match pieces.as_slice() {
+            [] => true,
+            [piece] => matches!(piece.piece_type, PieceType::Bishop | PieceType::Knight),
+            _ => {
+                let square_color = |piece: &Piece| (piece.position.x + piece.position.y) % 2;
+                pieces
+                    .iter()
+                    .all(|piece| piece.piece_type == PieceType::Bishop)
+                    && pieces
+                        .iter()
+                        .all(|piece| square_color(piece) == square_color(&pieces[0]))
+            }
+        }
+    }
+
+    /// Only kings and pawns are left, every pawn is stuck behind another pawn with nothing
+    /// to capture, and neither king can get to an enemy pawn that isn't protected.
+    /// Nothing can ever change on the board then, so there can be no checkmate
+    pub fn is_dead_position(&self) -> bool {
+        let pieces = self.get_pieces();
+        let pawn_at = |position: Position| {
+            check_bounds(position)
+                && self.pieces[position.x as usize][position.y as usize]
+                    .is_some_and(|piece| piece.piece_type == PieceType::Pawn)
+        };
+        let forward = |color: Color| match color {
+            Color::White => 1,
+            Color::Black => -1,
+        };
+        // Squares a pawn of the given color attacks from a position
+        let pawn_attacks = |position: Position, color: Color| {
+            [
+                position + (-1, forward(color)),
+                position + (1, forward(color)),
+            ]
+        };
+
+        let mut pawns = vec![];
+        for piece in &pieces {
+            match piece.piece_type {
+                PieceType::King => (),
+                PieceType::Pawn => pawns.push(*piece),
+                _ => return false,
+            }
+        }
+        if pawns.is_empty() {
+            return false;
+        }
+
+        for pawn in &pawns {
+            let blocked = pawn_at(pawn.position + (0, forward(pawn.color)));
+            let can_capture = pawn_attacks(pawn.position, pawn.color)
+                .iter()
+                .any(|target| check_bounds(*target) && self.color_at(*target) == Some(!pawn.color));
+            if !blocked || can_capture {
                 return false;
             }
         }
 
+        for king in pieces
+            .iter()
+            .filter(|piece| piece.piece_type == PieceType::King)
+        {
+            // Squares the king can never step on since an enemy pawn guards them
+            let guarded = |position: Position| {
+                pawns
+                    .iter()
+                    .filter(|pawn| pawn.color != king.color)
+                    .any(|pawn| pawn_attacks(pawn.position, pawn.color).contains(&position))
+            };
+
+            // Flood fill everywhere the king can walk to
+            let mut visited = [[false; 8]; 8];
+            let mut stack = vec![king.position];
+            visited[king.position.x as usize][king.position.y as usize] = true;
+            while let Some(position) = stack.pop() {
+                for dx in -1..=1 {
+                    for dy in -1..=1 {
+                        let next = position + (dx, dy);
+                        if !check_bounds(next) || visited[next.x as usize][next.y as usize] {
+                            continue;
+                        }
+                        visited[next.x as usize][next.y as usize] = true;
+
+                        if guarded(next) {
+                            continue;
+                        }
+                        match self.pieces[next.x as usize][next.y as usize] {
+                            Some(piece) if piece.piece_type == PieceType::Pawn => {
+                                if piece.color != king.color {
+                                    // Free pawn to eat, things can change
+                                    return false;
+                                }
+                            }
+                            _ => stack.push(next),
+                        }
+                    }
+                }
+            }
+        }
+
         true
     }
 
-    pub fn promote(&mut self, target: PieceType) {
+    /// Finishes a pending promotion from `move_piece`, returns false if there is nothing to promote
+    pub fn promote(&mut self, target: PieceType) -> bool {
         let to_upgrade = match self.status {
             GameStatus::Promotion(piece) => piece,
-            _ => panic!("Error!"),
+            _ => return false,
         };
 
-        self.pieces[to_upgrade.position.x as usize][to_upgrade.position.y as usize]
-            .as_mut()
-            .unwrap()
-            .piece_type = target;
+        if target == PieceType::King || target == PieceType::Pawn {
+            return false;
+        }
+
+        self.set_piece(
+            to_upgrade.position,
+            Some(Piece {
+                piece_type: target,
+                ..to_upgrade
+            }),
+        );
+        self.toggle_zobrist(to_upgrade, PieceType::Pawn, to_upgrade.position);
+        self.toggle_zobrist(to_upgrade, target, to_upgrade.position);
+
+        if let Some(entry) = self.history.last_mut() {
+            entry.mov.promotion = Some(target);
+            // The new piece can give a check the pawn didn't, so the variant sees the move again
+            let (mov, checks) = (entry.mov, entry.checks);
+            self.zobrist ^= self.state_key();
+            self.checks = checks;
+            let variant = self.variant;
+            variant.after_move(self, &mov);
+            self.zobrist ^= self.state_key();
+        }
 
         self.status = GameStatus::Active;
+        true
     }
 
-    // Updates internal game status and returns it, to be called after each move by a player
+    // Updates internal game status and returns it, to be called after each move by a player.
+    // Ends the game if the status or the clock says so
     pub fn update_game(&mut self) -> GameStatus {
+        if self.result.is_none() {
+            self.update_status();
+            self.result = GameResult::from_status(self.status).or(self.result());
+            if self.result.is_some() {
+                self.stop_clock();
+            }
+        }
+        self.status
+    }
+
+    fn update_status(&mut self) -> GameStatus {
         if let GameStatus::Promotion(_) = self.status {
             return self.status;
         }
 
+        if let Some(result) = self.variant.result(self) {
+            self.status = GameStatus::VariantEnd;
+            self.result = Some(result);
+            return self.status;
+        }
+
         if let Some(color) = self.is_checkmate() {
             self.status = GameStatus::Checkmate(color);
             return self.status;
         };
 
+        let repetitions = self.repetition_count();
+        if repetitions >= 5 {
+            self.status = GameStatus::FivefoldRepetition;
+            return self.status;
+        }
+
+        if self.halfmove_clock >= 150 {
+            self.status = GameStatus::SeventyFiveMoveRule;
+            return self.status;
+        }
+
+        if self.variant.is_insufficient_material(self) {
+            self.status = GameStatus::InsufficientMaterial;
+            return self.status;
+        }
+
+        if self.variant.is_dead_position(self) {
+            self.status = GameStatus::DeadPosition;
+            return self.status;
+        }
+
         if let Some(color) = self.is_check() {
             self.status = GameStatus::Check(color);
             return self.status;
@@ -416,7 +931,12 @@ impl Game {
             return self.status;
         }
 
-        if self.moves_since_capture == 50 {
+        if repetitions >= 3 {
+            self.status = GameStatus::ThreefoldRepetition;
+            return self.status;
+        }
+
+        if self.halfmove_clock >= 100 {
             self.status = GameStatus::FiftyMoveRule;
             return self.status;
         }
@@ -426,6 +946,12 @@ impl Game {
     }
 }
 
+impl Default for Game {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Piece {
     pub color: Color,
@@ -434,6 +960,16 @@ pub struct Piece {
     pub num_moves: u32,
 }
 
+impl Piece {
+    /// FEN letter for the piece, uppercase for white and lowercase for black
+    pub fn to_char(&self) -> char {
+        match self.color {
+            Color::White => self.piece_type.to_char(),
+            Color::Black => self.piece_type.to_char().to_ascii_lowercase(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Position {
     pub x: i8,
@@ -448,11 +984,17 @@ impl FromStr for Position {
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let chars: Vec<char> = s.chars().collect();
+        if chars.len() != 2 {
+            return Err(ParsePositionError);
+        }
 
-        let x = chars[0].to_ascii_lowercase() as u8 - b'a';
-        let y = chars[1].to_digit(10).unwrap_or(0) - 1;
+        let file = chars[0].to_ascii_lowercase();
+        let rank = chars[1].to_digit(10).ok_or(ParsePositionError)?;
+        if !('a'..='h').contains(&file) || !(1..=8).contains(&rank) {
+            return Err(ParsePositionError);
+        }
 
-        Ok((x as i8, y as i8).into())
+        Ok(((file as u8 - b'a') as i8, rank as i8 - 1).into())
     }
 }
 
@@ -460,7 +1002,7 @@ use std::fmt;
 
 impl fmt::Display for Position {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let x = ((self.x as u8 + b'A') as char).to_string();
+        let x = ((self.x as u8 + b'a') as char).to_string();
         let y = (self.y + 1).to_string();
 
         write!(f, "{}{}", x, y)
@@ -527,6 +1069,144 @@ pub enum PieceType {
     Pawn,
 }
 
+impl PieceType {
+    /// Uppercase letter used for the piece type in FEN and algebraic notation
+    pub fn to_char(self) -> char {
+        match self {
+            PieceType::King => 'K',
+            PieceType::Queen => 'Q',
+            PieceType::Rook => 'R',
+            PieceType::Bishop => 'B',
+            PieceType::Knight => 'N',
+            PieceType::Pawn => 'P',
+        }
+    }
+
+    /// Parses a piece letter, ignoring case
+    pub fn from_char(c: char) -> Option<PieceType> {
+        match c.to_ascii_lowercase() {
+            'k' => Some(PieceType::King),
+            'q' => Some(PieceType::Queen),
+            'r' => Some(PieceType::Rook),
+            'b' => Some(PieceType::Bishop),
+            'n' => Some(PieceType::Knight),
+            'p' => Some(PieceType::Pawn),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CastlingSide {
+    /// Kingside, king ends up on the g file
+    Short,
+    /// Queenside, king ends up on the c file
+    Long,
+}
+
+impl CastlingSide {
+    /// File the king ends up on, in Chess960 too
+    pub fn king_file(self) -> i8 {
+        match self {
+            CastlingSide::Short => 6,
+            CastlingSide::Long => 2,
+        }
+    }
+
+    /// File the rook ends up on
+    pub fn rook_file(self) -> i8 {
+        match self {
+            CastlingSide::Short => 5,
+            CastlingSide::Long => 3,
+        }
+    }
+}
+
+/// Files the king and the castling rooks start on, for each color. Always the e, h and a
+/// files except in Chess960
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CastlingFiles {
+    /// Indexed by `Color as usize`
+    pub king: [i8; 2],
+    /// Indexed by `Color as usize` and then `CastlingSide as usize`
+    pub rooks: [[i8; 2]; 2],
+}
+
+impl Default for CastlingFiles {
+    fn default() -> Self {
+        Self {
+            king: [4, 4],
+            rooks: [[7, 0], [7, 0]],
+        }
+    }
+}
+
+impl CastlingFiles {
+    pub fn king(&self, color: Color) -> i8 {
+        self.king[color as usize]
+    }
+
+    pub fn rook(&self, color: Color, side: CastlingSide) -> i8 {
+        self.rooks[color as usize][side as usize]
+    }
+}
+
+/// Which castling moves are still available to each player
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct CastlingRights {
+    pub white_short: bool,
+    pub white_long: bool,
+    pub black_short: bool,
+    pub black_long: bool,
+}
+
+impl CastlingRights {
+    pub fn all() -> Self {
+        Self {
+            white_short: true,
+            white_long: true,
+            black_short: true,
+            black_long: true,
+        }
+    }
+
+    pub fn has(&self, color: Color, side: CastlingSide) -> bool {
+        match (color, side) {
+            (Color::White, CastlingSide::Short) => self.white_short,
+            (Color::White, CastlingSide::Long) => self.white_long,
+            (Color::Black, CastlingSide::Short) => self.black_short,
+            (Color::Black, CastlingSide::Long) => self.black_long,
+        }
+    }
+
+    pub fn set(&mut self, color: Color, side: CastlingSide, value: bool) {
+        match (color, side) {
+            (Color::White, CastlingSide::Short) => self.white_short = value,
+            (Color::White, CastlingSide::Long) => self.white_long = value,
+            (Color::Black, CastlingSide::Short) => self.black_short = value,
+            (Color::Black, CastlingSide::Long) => self.black_long = value,
+        }
+    }
+
+    /// Removes the rights affected by something moving from or to the given squares,
+    /// which covers king moves, rook moves and rooks being captured on their home square
+    pub fn update(&mut self, files: &CastlingFiles, from: Position, to: Position) {
+        for position in [from, to] {
+            for (color, y) in [(Color::White, 0), (Color::Black, 7)] {
+                if position.y != y {
+                    continue;
+                }
+
+                for side in [CastlingSide::Short, CastlingSide::Long] {
+                    if position.x == files.king(color) || position.x == files.rook(color, side) {
+                        self.set(color, side, false);
+                    }
+                }
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Default)]
 pub enum Color {
     #[default]
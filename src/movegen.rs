@@ -0,0 +1,403 @@
+use crate::{
+    bitboard::{self, attacks, squares, Bitboard, BETWEEN, KING_ATTACKS, LINE, PAWN_ATTACKS},
+    crazyhouse::drop_move,
+    moves::{is_promotion, IllegalMove, Move, MoveList, MoveType, PROMOTIONS},
+    CastlingSide, Color, Game, Piece, PieceType, Position,
+};
+
+/// What a color has to respect for its moves to be legal, worked out once per position
+/// so no move has to be tried out on a copy of the board
+struct Constraints {
+    king: Option<usize>,
+    /// Pieces giving check
+    checkers: Bitboard,
+    /// Squares that deal with a single check, by taking the checker or getting in between.
+    /// Every square when not in check
+    evasions: Bitboard,
+    /// Own pieces that would expose the king by leaving the line to an enemy slider
+    pinned: Bitboard,
+}
+
+impl Game {
+    /// Every legal move of a color, with one move per promotion choice and no check flags
+    pub(crate) fn generate_moves(&self, color: Color, moves: &mut MoveList) {
+        let constraints = self.constraints(color);
+        for from in squares(self.bitboards.by_color[color as usize]) {
+            self.generate_piece_moves(bitboard::position(from), &constraints, moves);
+        }
+        if self.variant.drops() {
+            self.generate_drops(color, &constraints, moves);
+        }
+        self.variant.filter_moves(self, moves);
+    }
+
+    /// Legal moves of whatever piece stands on a square
+    pub(crate) fn generate_moves_from(&self, from: Position, moves: &mut MoveList) {
+        if let Some(piece) = self.pieces[from.x as usize][from.y as usize] {
+            let constraints = self.constraints(piece.color);
+            self.generate_piece_moves(from, &constraints, moves);
+            self.variant.filter_moves(self, moves);
+        }
+    }
+
+    /// Whether a move would be legal in standard chess, without the variant filtering it out
+    pub(crate) fn legal_without_variant(&self, from: Position, to: Position) -> bool {
+        let mut moves = MoveList::new();
+        if let Some(piece) = self.pieces[from.x as usize][from.y as usize] {
+            self.generate_piece_moves(from, &self.constraints(piece.color), &mut moves);
+        }
+        moves.iter().any(|mov| mov.to == to)
+    }
+
+    fn constraints(&self, color: Color) -> Constraints {
+        let bitboards = &self.bitboards;
+        let us = bitboards.by_color[color as usize];
+        let them = bitboards.by_color[!color as usize];
+        let occupied = us | them;
+
+        let king = match squares(bitboards.pieces(color, PieceType::King)).next() {
+            Some(king) => king,
+            // Without a king there is nothing to protect
+            None => {
+                return Constraints {
+                    king: None,
+                    checkers: 0,
+                    evasions: !0,
+                    pinned: 0,
+                }
+            }
+        };
+
+        let checkers = bitboards.attackers(king, occupied) & them;
+        let evasions = match checkers.count_ones() {
+            0 => !0,
+            1 => checkers | BETWEEN[king][checkers.trailing_zeros() as usize],
+            // Double check, only the king can move
+            _ => 0,
+        };
+
+        // Enemy sliders that would hit the king if exactly one own piece got out of the way
+        let straight =
+            bitboards.pieces(!color, PieceType::Rook) | bitboards.pieces(!color, PieceType::Queen);
+        let diagonal = bitboards.pieces(!color, PieceType::Bishop)
+            | bitboards.pieces(!color, PieceType::Queen);
+        let snipers = bitboard::rook_attacks(king, 0) & straight
+            | bitboard::bishop_attacks(king, 0) & diagonal;
+
+        let mut pinned = 0;
+        for sniper in squares(snipers) {
+            let blockers = BETWEEN[king][sniper] & occupied;
+            if blockers.count_ones() == 1 {
+                pinned |= blockers & us;
+            }
+        }
+
+        Constraints {
+            king: Some(king),
+            checkers,
+            evasions,
+            pinned,
+        }
+    }
+
+    fn generate_piece_moves(
+        &self,
+        from: Position,
+        constraints: &Constraints,
+        moves: &mut MoveList,
+    ) {
+        let piece = match self.pieces[from.x as usize][from.y as usize] {
+            Some(piece) => piece,
+            None => return,
+        };
+
+        let us = self.bitboards.by_color[piece.color as usize];
+        let them = self.bitboards.by_color[!piece.color as usize];
+        let occupied = us | them;
+        let square = bitboard::square(from);
+
+        if piece.piece_type == PieceType::King {
+            for to in squares(KING_ATTACKS[square] & !us) {
+                // Sliders see through the square the king leaves, so it can't step back
+                // along the ray
+                if !self
+                    .bitboards
+                    .is_attacked(to, !piece.color, occupied ^ 1 << square)
+                {
+                    self.push_moves(piece, bitboard::position(to), moves);
+                }
+            }
+
+            if constraints.checkers == 0 {
+                self.generate_castling(piece, moves);
+            }
+            return;
+        }
+
+        let mut targets = match piece.piece_type {
+            PieceType::Pawn => self.pawn_targets(piece, square, occupied, them),
+            piece_type => attacks(piece_type, piece.color, square, occupied) & !us,
+        };
+
+        if let Some(king) = constraints.king {
+            if constraints.pinned & 1 << square != 0 {
+                targets &= LINE[king][square];
+            }
+        }
+
+        let en_passant = self.en_passant_target(piece.color);
+        for to in squares(targets) {
+            let to = bitboard::position(to);
+            if piece.piece_type == PieceType::Pawn && en_passant == Some(to) {
+                if self.en_passant_legal(piece, to, constraints) {
+                    self.push_moves(piece, to, moves);
+                }
+            } else if constraints.evasions & bitboard::bit(to) != 0 {
+                self.push_moves(piece, to, moves);
+            }
+        }
+    }
+
+    fn pawn_targets(
+        &self,
+        pawn: Piece,
+        square: usize,
+        occupied: Bitboard,
+        them: Bitboard,
+    ) -> Bitboard {
+        let (forward, start_rank) = match pawn.color {
+            Color::White => (1, 1),
+            Color::Black => (-1, 6),
+        };
+
+        let mut targets = 0;
+        let one = pawn.position + (0, forward);
+        if bitboard::bit(one) & occupied == 0 {
+            targets |= bitboard::bit(one);
+
+            let two = one + (0, forward);
+            if pawn.position.y == start_rank && bitboard::bit(two) & occupied == 0 {
+                targets |= bitboard::bit(two);
+            }
+        }
+
+        let mut capturable = them;
+        if let Some(target) = self.en_passant_target(pawn.color) {
+            capturable |= bitboard::bit(target);
+        }
+        targets | PAWN_ATTACKS[pawn.color as usize][square] & capturable
+    }
+
+    /// The square a pawn of the given color can take en passant on, if any
+    pub(crate) fn en_passant_target(&self, color: Color) -> Option<Position> {
+        let passed = self
+            .en_passant_possible
+            .filter(|pawn| pawn.color != color)?;
+        let target = match passed.color {
+            Color::White => passed.position - (0, 1),
+            Color::Black => passed.position + (0, 1),
+        };
+        self.color_at(target).is_none().then_some(target)
+    }
+
+    /// En passant takes two pawns off the same rank at once, which pins don't cover. It can
+    /// also answer a check from the pawn that just moved even though `to` isn't the checker,
+    /// so the king is looked at from scratch with the board as it would be afterwards
+    fn en_passant_legal(&self, pawn: Piece, to: Position, constraints: &Constraints) -> bool {
+        let king = match constraints.king {
+            Some(king) => king,
+            None => return true,
+        };
+        let passed = bitboard::bit(self.en_passant_possible.unwrap().position);
+
+        let occupied =
+            self.bitboards.occupied() ^ bitboard::bit(pawn.position) ^ passed | bitboard::bit(to);
+        let them = self.bitboards.by_color[!pawn.color as usize] ^ passed;
+        self.bitboards.attackers(king, occupied) & them == 0
+    }
+
+    /// Pieces from the pocket can go on any empty square, as long as it blocks a check if
+    /// there is one. Pawns can't go on the first or last rank
+    fn generate_drops(&self, color: Color, constraints: &Constraints, moves: &mut MoveList) {
+        const BACK_RANKS: Bitboard = 0xFF | 0xFF << 56;
+
+        let empty = !self.bitboards.occupied() & constraints.evasions;
+        for piece_type in self.pockets[color as usize].piece_types() {
+            let targets = match piece_type {
+                PieceType::Pawn => empty & !BACK_RANKS,
+                _ => empty,
+            };
+            for to in squares(targets) {
+                moves.push(drop_move(color, piece_type, bitboard::position(to)));
+            }
+        }
+    }
+
+    /// Adds a move known to be legal, once for every promotion choice if it promotes
+    fn push_moves(&self, piece: Piece, to: Position, moves: &mut MoveList) {
+        let (move_type, captured) = match self.pieces[to.x as usize][to.y as usize] {
+            Some(captured) => (MoveType::Attack, Some(captured)),
+            None if piece.piece_type == PieceType::Pawn
+                && self.en_passant_target(piece.color) == Some(to) =>
+            {
+                (MoveType::EnPassant, self.en_passant_possible)
+            }
+            None => (MoveType::Regular, None),
+        };
+
+        let mov = Move {
+            from: piece.position,
+            to,
+            piece,
+            captured,
+            promotion: None,
+            move_type,
+            check: false,
+            checkmate: false,
+        };
+        if is_promotion(piece, to) {
+            for promotion in PROMOTIONS {
+                moves.push(Move {
+                    promotion: Some(promotion),
+                    ..mov
+                });
+            }
+        } else {
+            moves.push(mov);
+        }
+    }
+
+    /// Castling moves for a king that is not in check
+    fn generate_castling(&self, king: Piece, moves: &mut MoveList) {
+        for side in [CastlingSide::Short, CastlingSide::Long] {
+            if self.castling_problem(king.color, side).is_some() {
+                continue;
+            }
+
+            let (king_to, rook_from, _) = self.castling_squares(king.color, side);
+            moves.push(Move {
+                from: king.position,
+                to: match self.chess960 {
+                    true => rook_from,
+                    false => king_to,
+                },
+                piece: king,
+                captured: None,
+                promotion: None,
+                move_type: MoveType::Castling,
+                check: false,
+                checkmate: false,
+            });
+        }
+    }
+
+    /// Where the king ends up, where the rook starts and where the rook ends up
+    pub(crate) fn castling_squares(
+        &self,
+        color: Color,
+        side: CastlingSide,
+    ) -> (Position, Position, Position) {
+        let home_rank = match color {
+            Color::White => 0,
+            Color::Black => 7,
+        };
+        (
+            (side.king_file(), home_rank).into(),
+            (self.castling_files.rook(color, side), home_rank).into(),
+            (side.rook_file(), home_rank).into(),
+        )
+    }
+
+    /// Why a color can't castle to a side right now, None if it can
+    pub(crate) fn castling_problem(&self, color: Color, side: CastlingSide) -> Option<IllegalMove> {
+        let (king_to, rook_from, rook_to) = self.castling_squares(color, side);
+        let king_from: Position = (self.castling_files.king(color), king_to.y).into();
+
+        let at_home = |position: Position, piece_type: PieceType| {
+            self.pieces[position.x as usize][position.y as usize]
+                .is_some_and(|piece| piece.color == color && piece.piece_type == piece_type)
+        };
+        if !self.castling.has(color, side)
+            || !at_home(king_from, PieceType::King)
+            || !at_home(rook_from, PieceType::Rook)
+        {
+            return Some(IllegalMove::CastlingRightsLost);
+        }
+
+        // Everything the king and rook pass over or land on has to be empty, apart from
+        // the king and rook themselves
+        let files = [king_from.x, king_to.x, rook_from.x, rook_to.x];
+        let (min, max) = (*files.iter().min().unwrap(), *files.iter().max().unwrap());
+        if (min..=max).any(|x| {
+            x != king_from.x && x != rook_from.x && self.color_at((x, king_to.y).into()).is_some()
+        }) {
+            return Some(IllegalMove::PathBlocked);
+        }
+
+        // The castling rook can be hiding an attack along the rank in Chess960
+        let occupied =
+            self.bitboards.occupied() ^ bitboard::bit(king_from) ^ bitboard::bit(rook_from);
+        let attacked = |x: i8| {
+            self.bitboards
+                .is_attacked(bitboard::square((x, king_to.y).into()), !color, occupied)
+        };
+        let step = (king_to.x - king_from.x).signum();
+        let mut x = king_from.x;
+        while x != king_to.x {
+            if attacked(x) {
+                return Some(IllegalMove::CastlingThroughCheck);
+            }
+            x += step;
+        }
+        if attacked(king_to.x) {
+            return Some(IllegalMove::LeavesKingInCheck);
+        }
+
+        None
+    }
+
+    /// The side a king move would castle to, if it looks like castling. That's the king
+    /// taking its own rook in Chess960 and the king moving two squares otherwise
+    pub(crate) fn castling_side(&self, king: Piece, to: Position) -> Option<CastlingSide> {
+        let home_rank = match king.color {
+            Color::White => 0,
+            Color::Black => 7,
+        };
+        if king.piece_type != PieceType::King
+            || king.position != (self.castling_files.king(king.color), home_rank).into()
+            || to.y != home_rank
+        {
+            return None;
+        }
+
+        let side = match to.x > king.position.x {
+            true => CastlingSide::Short,
+            false => CastlingSide::Long,
+        };
+        let castles = match self.chess960 {
+            true => {
+                to.x == self.castling_files.rook(king.color, side)
+                    && self.pieces[to.x as usize][to.y as usize].is_some_and(|rook| {
+                        rook.color == king.color && rook.piece_type == PieceType::Rook
+                    })
+            }
+            false => (to.x - king.position.x).abs() == 2,
+        };
+        castles.then_some(side)
+    }
+
+    /// Lets castling be entered as the king taking its own rook outside of Chess960 too
+    pub(crate) fn castling_input(&self, piece: Piece, to: Position) -> Position {
+        if self.chess960 || piece.piece_type != PieceType::King {
+            return to;
+        }
+
+        for side in [CastlingSide::Short, CastlingSide::Long] {
+            let (king_to, rook_from, _) = self.castling_squares(piece.color, side);
+            if to == rook_from && self.castling_side(piece, king_to) == Some(side) {
+                return king_to;
+            }
+        }
+        to
+    }
+}
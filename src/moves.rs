@@ -1,4 +1,9 @@
-use crate::{Color, Game, Piece, PieceType, Position};
+use crate::{
+    bitboard::{self, attacks, PAWN_ATTACKS},
+    CastlingSide, Color, Game, Piece, PieceType, Position,
+};
+
+use std::{fmt, ops};
 
 pub type Moves = Vec<Position>;
 
@@ -9,302 +14,296 @@ pub fn check_bounds(position: Position) -> bool {
     true
 }
 
-fn push_if_valid_bounds(moves: &mut Moves, position: Position) {
-    if check_bounds(position) {
-        moves.push(position);
-    }
-}
-
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum MoveType {
     Invalid,
     Regular,
     Attack,
     EnPassant,
     Castling,
+    /// A piece put on the board from the pocket, in variants with drops
+    Drop,
 }
 
-/// Does not check piece-specific movement requirements
-pub fn get_move_type(game: &Game, piece: Piece, position: Position) -> MoveType {
-    if !check_bounds(position) {
-        return MoveType::Invalid;
-    }
+/// Pieces a pawn can promote to
+pub const PROMOTIONS: [PieceType; 4] = [
+    PieceType::Queen,
+    PieceType::Rook,
+    PieceType::Bishop,
+    PieceType::Knight,
+];
+
+/// A single move of a piece along with everything needed to describe it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Move {
+    pub from: Position,
+    pub to: Position,
+    /// The moving piece as it was before the move
+    pub piece: Piece,
+    /// The captured piece, which for en passant is not on `to`
+    pub captured: Option<Piece>,
+    /// Only set for pawns reaching the last rank
+    pub promotion: Option<PieceType>,
+    pub move_type: MoveType,
+    /// Whether the move checks the opponent
+    pub check: bool,
+    pub checkmate: bool,
+}
 
-    // En passant
-    if piece.piece_type == PieceType::Pawn
-        && game
-            .en_passant_possible
-            .is_some_and(|piece2| piece2.color != piece.color)
-    {
-        let to_pass = game.en_passant_possible.unwrap();
-        let mut behind = to_pass.position;
-        if to_pass.color == Color::White {
-            behind = behind - (0, 1);
-        } else {
-            behind = behind + (0, 1);
+impl Move {
+    /// Which way the move castles, if it does
+    pub fn castling_side(&self) -> Option<CastlingSide> {
+        match (self.move_type, self.to.x > self.from.x) {
+            (MoveType::Castling, true) => Some(CastlingSide::Short),
+            (MoveType::Castling, false) => Some(CastlingSide::Long),
+            _ => None,
         }
+    }
+}
 
-        if position == behind && game.color_at(behind).is_none() {
-            return MoveType::EnPassant;
+/// No standard position has more than 218 legal moves, drops can add a few hundred more
+pub const MAX_MOVES: usize = 512;
+
+/// Placeholder for the unused slots of a `MoveList`
+const NO_MOVE: Move = Move {
+    from: Position { x: 0, y: 0 },
+    to: Position { x: 0, y: 0 },
+    piece: Piece {
+        color: Color::White,
+        piece_type: PieceType::Pawn,
+        position: Position { x: 0, y: 0 },
+        num_moves: 0,
+    },
+    captured: None,
+    promotion: None,
+    move_type: MoveType::Invalid,
+    check: false,
+    checkmate: false,
+};
+
+/// Fixed capacity list of moves, so generating moves doesn't need the heap
+#[derive(Debug, Clone, Copy)]
+pub struct MoveList {
+    moves: [Move; MAX_MOVES],
+    len: usize,
+}
+
+impl MoveList {
+    pub fn new() -> Self {
+        Self {
+            moves: [NO_MOVE; MAX_MOVES],
+            len: 0,
         }
     }
 
-    // Castling (king moved 2 squares <=> castling)
-    if piece.piece_type == PieceType::King && (piece.position - position).x.abs() == 2 {
-        return MoveType::Castling;
+    pub fn push(&mut self, mov: Move) {
+        self.moves[self.len] = mov;
+        self.len += 1;
     }
 
-    match game.color_at(position) {
-        Some(color) => {
-            if color != piece.color {
-                return MoveType::Attack;
+    /// Keeps only the moves the closure returns true for, in the same order
+    pub fn retain(&mut self, mut keep: impl FnMut(&Move) -> bool) {
+        let mut len = 0;
+        for i in 0..self.len {
+            if keep(&self.moves[i]) {
+                self.moves[len] = self.moves[i];
+                len += 1;
             }
         }
-        None => return MoveType::Regular,
+        self.len = len;
     }
-
-    MoveType::Invalid
 }
 
-fn push_if_valid_attack(moves: &mut Moves, game: &Game, piece: Piece, delta: Position) {
-    let new_position = piece.position + delta;
-    match get_move_type(game, piece, new_position) {
-        MoveType::Invalid => (),
-        _ => moves.push(new_position),
+impl Default for MoveList {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
-pub fn get_pseudo_moves(game: &Game, piece: Piece) -> Moves {
-    match piece.piece_type {
-        PieceType::Pawn => get_pawn_moves(game, piece),
-        PieceType::Knight => get_knight_moves(game, piece),
-        PieceType::Bishop => get_bishop_moves(game, piece),
-        PieceType::Rook => get_rook_moves(game, piece),
-        PieceType::Queen => get_queen_moves(game, piece),
-        PieceType::King => get_king_moves(game, piece),
-    }
-}
+impl ops::Deref for MoveList {
+    type Target = [Move];
 
-fn get_pawn_moves(game: &Game, piece: Piece) -> Moves {
-    let mut moves: Moves = vec![];
-
-    let mut left = piece.position;
-    let mut right = piece.position;
-    let mut forward = piece.position;
-    if piece.color == Color::White {
-        left = left + (1, 1);
-        right = right + (-1, 1);
-        forward = forward + (0, 1);
-    } else {
-        left = left - (1, 1);
-        right = right - (-1, 1);
-        forward = forward - (0, 1);
-    }
-
-    let mut forward_valid = false;
-
-    // Single step forward
-    if get_move_type(game, piece, forward) == MoveType::Regular {
-        moves.push(forward);
-        forward_valid = true;
+    fn deref(&self) -> &[Move] {
+        &self.moves[..self.len]
     }
+}
 
-    // Sideways capture or en passant
-    if get_move_type(game, piece, left) == MoveType::Attack
-        || get_move_type(game, piece, left) == MoveType::EnPassant
-    {
-        moves.push(left);
-    }
+impl IntoIterator for MoveList {
+    type Item = Move;
+    type IntoIter = std::iter::Take<std::array::IntoIter<Move, MAX_MOVES>>;
 
-    if get_move_type(game, piece, right) == MoveType::Attack
-        || get_move_type(game, piece, right) == MoveType::EnPassant
-    {
-        moves.push(right);
+    fn into_iter(self) -> Self::IntoIter {
+        self.moves.into_iter().take(self.len)
     }
+}
 
-    /* let initial_move = (piece.position.y == 1 && piece.color == Color::White)
-    || (piece.position.y == 6 && piece.color == Color::Black); */
-    let initial_move = piece.num_moves == 0;
-
-    // Double step forward
-    // FIX: Bug here where player can capture own pieces at double step forward
-    // Update: FIXED!
-    if initial_move && forward_valid {
-        forward = match piece.color {
-            Color::White => piece.position + (0, 2),
-            Color::Black => piece.position - (0, 2),
-        };
+impl<'a> IntoIterator for &'a MoveList {
+    type Item = &'a Move;
+    type IntoIter = std::slice::Iter<'a, Move>;
 
-        if get_move_type(game, piece, forward) == MoveType::Regular {
-            push_if_valid_bounds(&mut moves, forward);
-        }
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
     }
-
-    moves
 }
 
-fn get_knight_moves(game: &Game, piece: Piece) -> Moves {
-    let mut moves: Moves = vec![];
-
-    push_if_valid_attack(&mut moves, game, piece, (2, 1).into());
-    push_if_valid_attack(&mut moves, game, piece, (2, -1).into());
-    push_if_valid_attack(&mut moves, game, piece, (-2, 1).into());
-    push_if_valid_attack(&mut moves, game, piece, (-2, -1).into());
-    push_if_valid_attack(&mut moves, game, piece, (1, 2).into());
-    push_if_valid_attack(&mut moves, game, piece, (1, -2).into());
-    push_if_valid_attack(&mut moves, game, piece, (-1, 2).into());
-    push_if_valid_attack(&mut moves, game, piece, (-1, -2).into());
-
-    moves
+/// Why a move was turned down
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IllegalMove {
+    NoPiece,
+    /// The piece belongs to the player who isn't to move
+    WrongSide,
+    /// The piece doesn't move like that, even on an empty board
+    InvalidMovement,
+    /// Something is standing in the way, or an own piece is on the target square
+    PathBlocked,
+    /// The move would leave or put the own king in check
+    LeavesKingInCheck,
+    /// Castling out of check or over an attacked square
+    CastlingThroughCheck,
+    /// The king or the rook has moved, or the rook is gone
+    CastlingRightsLost,
+    /// The promotion piece is missing, can't be promoted to, or the move doesn't promote
+    InvalidPromotion,
+    /// A promotion has to be finished with `promote` first
+    PromotionPending,
+    /// The piece isn't in the pocket of the player to move
+    NotInPocket,
+    /// Drops aren't allowed in the variant, or a pawn would go on the first or last rank
+    InvalidDrop,
+    /// The variant's rules don't allow it, like giving check in Racing Kings
+    VariantRule,
+    GameOver,
 }
 
-fn get_moves_direction(game: &Game, piece: Piece, direction: Position) -> Moves {
-    let mut moves: Moves = vec![];
-
-    let mut pos = piece.position + direction;
-    while check_bounds(pos) {
-        match get_move_type(game, piece, pos) {
-            MoveType::Attack => {
-                moves.push(pos);
-                break;
-            }
-            MoveType::Regular => (),
-            _ => break,
-        }
-
-        moves.push(pos);
-        pos = pos + direction;
+impl fmt::Display for IllegalMove {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self {
+            IllegalMove::NoPiece => "there is no piece on that square",
+            IllegalMove::WrongSide => "it is not that player's turn",
+            IllegalMove::InvalidMovement => "the piece can't move like that",
+            IllegalMove::PathBlocked => "the path is blocked",
+            IllegalMove::LeavesKingInCheck => "the move would leave the king in check",
+            IllegalMove::CastlingThroughCheck => "can't castle out of or through check",
+            IllegalMove::CastlingRightsLost => "castling is no longer allowed on that side",
+            IllegalMove::InvalidPromotion => "invalid promotion",
+            IllegalMove::PromotionPending => "a promotion has to be chosen first",
+            IllegalMove::NotInPocket => "that piece is not in the pocket",
+            IllegalMove::InvalidDrop => "the piece can't be dropped there",
+            IllegalMove::VariantRule => "the variant doesn't allow that move",
+            IllegalMove::GameOver => "the game is already over",
+        };
+        write!(f, "{}", reason)
     }
-
-    moves
 }
 
-fn get_bishop_moves(game: &Game, piece: Piece) -> Moves {
-    let mut moves: Moves = vec![];
+impl std::error::Error for IllegalMove {}
 
-    moves.append(&mut get_moves_direction(game, piece, (1, 1).into()));
-    moves.append(&mut get_moves_direction(game, piece, (-1, 1).into()));
-    moves.append(&mut get_moves_direction(game, piece, (1, -1).into()));
-    moves.append(&mut get_moves_direction(game, piece, (-1, -1).into()));
-
-    moves
-}
-
-fn get_rook_moves(game: &Game, piece: Piece) -> Moves {
-    let mut moves: Moves = vec![];
-
-    moves.append(&mut get_moves_direction(game, piece, (1, 0).into()));
-    moves.append(&mut get_moves_direction(game, piece, (0, 1).into()));
-    moves.append(&mut get_moves_direction(game, piece, (-1, 0).into()));
-    moves.append(&mut get_moves_direction(game, piece, (0, -1).into()));
-
-    moves
+/// Whether moving a piece to a position takes a pawn to the last rank
+pub fn is_promotion(piece: Piece, to: Position) -> bool {
+    piece.piece_type == PieceType::Pawn
+        && ((to.y == 0 && piece.color == Color::Black)
+            || (to.y == 7 && piece.color == Color::White))
 }
 
-fn get_queen_moves(game: &Game, piece: Piece) -> Moves {
-    let mut moves: Moves = vec![];
+impl Game {
+    /// Works out why a piece of the player to move can't go to a square. Only makes sense
+    /// for moves that are already known to be illegal
+    pub(crate) fn illegal_reason(&self, piece: Piece, to: Position) -> IllegalMove {
+        if !check_bounds(to) || to == piece.position {
+            return IllegalMove::InvalidMovement;
+        }
 
-    moves.append(&mut get_moves_direction(game, piece, (1, 0).into()));
-    moves.append(&mut get_moves_direction(game, piece, (0, 1).into()));
-    moves.append(&mut get_moves_direction(game, piece, (-1, 0).into()));
-    moves.append(&mut get_moves_direction(game, piece, (0, -1).into()));
+        if let Some(side) = self.castling_side(piece, to) {
+            return self
+                .castling_problem(piece.color, side)
+                .unwrap_or(IllegalMove::LeavesKingInCheck);
+        }
 
-    moves.append(&mut get_moves_direction(game, piece, (1, 1).into()));
-    moves.append(&mut get_moves_direction(game, piece, (-1, 1).into()));
-    moves.append(&mut get_moves_direction(game, piece, (1, -1).into()));
-    moves.append(&mut get_moves_direction(game, piece, (-1, -1).into()));
+        let occupied = self.bitboards.occupied();
+
+        // Whether the piece moves like that at all, and whether it's free to
+        let from = bitboard::square(piece.position);
+        let target = bitboard::bit(to);
+        let (reachable, free) = match piece.piece_type {
+            PieceType::Pawn => {
+                let (forward, start_rank) = match piece.color {
+                    Color::White => (1, 1),
+                    Color::Black => (-1, 6),
+                };
+                let one = piece.position + (0, forward);
+                let two = one + (0, forward);
+
+                if to == one {
+                    (true, occupied & target == 0)
+                } else if to == two && piece.position.y == start_rank {
+                    (true, occupied & (target | bitboard::bit(one)) == 0)
+                } else if PAWN_ATTACKS[piece.color as usize][from] & target != 0 {
+                    // Pawns only go diagonally when taking something
+                    match self.color_at(to) {
+                        Some(color) => (true, color != piece.color),
+                        None => (self.en_passant_target(piece.color) == Some(to), true),
+                    }
+                } else {
+                    (false, false)
+                }
+            }
+            piece_type => (
+                attacks(piece_type, piece.color, from, 0) & target != 0,
+                attacks(piece_type, piece.color, from, occupied) & target != 0
+                    && self.color_at(to) != Some(piece.color),
+            ),
+        };
 
-    moves
+        if !reachable {
+            IllegalMove::InvalidMovement
+        } else if !free {
+            IllegalMove::PathBlocked
+        } else if self.legal_without_variant(piece.position, to) {
+            IllegalMove::VariantRule
+        } else {
+            IllegalMove::LeavesKingInCheck
+        }
+    }
 }
 
-fn get_king_moves(game: &Game, piece: Piece) -> Moves {
-    let mut moves: Moves = vec![];
-
-    push_if_valid_attack(&mut moves, game, piece, (1, 0).into());
-    push_if_valid_attack(&mut moves, game, piece, (0, 1).into());
-    push_if_valid_attack(&mut moves, game, piece, (-1, 0).into());
-    push_if_valid_attack(&mut moves, game, piece, (0, -1).into());
-
-    push_if_valid_attack(&mut moves, game, piece, (1, 1).into());
-    push_if_valid_attack(&mut moves, game, piece, (-1, 1).into());
-    push_if_valid_attack(&mut moves, game, piece, (1, -1).into());
-    push_if_valid_attack(&mut moves, game, piece, (-1, -1).into());
-
-    // Time for castling :skull:
-    // Makes the most sense to define castling as a kings move
-    // If you dont agree im sorry but you're wrong
-    // PS: dont read any of the code below it is absolutely horrendous
-
-    let pieces = game.get_pieces();
-    let king = pieces
-        .iter()
-        .find(|piece2| piece2.color == piece.color && piece2.num_moves == 0)
-        .cloned();
-
-    if king.is_none() {
-        // King has moved or something else has gone terribly wrong
-        return moves;
+/// Does not check piece-specific movement requirements
+pub fn get_move_type(game: &Game, piece: Piece, position: Position) -> MoveType {
+    if !check_bounds(position) {
+        return MoveType::Invalid;
     }
 
-    // Currently causes stack overflow :skull:
-    // Check if king is checked without generating moves for opposing king
-    // The opposing king cannot interfere with the castling anyway
-    if game.is_color_checked_exclude_king(piece.color) {
-        return moves;
-    }
+    // En passant
+    if piece.piece_type == PieceType::Pawn
+        && game
+            .en_passant_possible
+            .is_some_and(|piece2| piece2.color != piece.color)
+    {
+        let to_pass = game.en_passant_possible.unwrap();
+        let mut behind = to_pass.position;
+        if to_pass.color == Color::White {
+            behind = behind - (0, 1);
+        } else {
+            behind = behind + (0, 1);
+        }
 
-    let long_rook = pieces
-        .iter()
-        .find(|piece2| {
-            piece2.piece_type == PieceType::Rook
-                && piece2.num_moves == 0
-                && piece2.position.x == 0
-                && piece2.color == piece.color
-        })
-        .cloned();
-    let short_rook = pieces
-        .iter()
-        .find(|piece2| {
-            piece2.piece_type == PieceType::Rook
-                && piece2.num_moves == 0
-                && piece2.position.x == 7
-                && piece2.color == piece.color
-        })
-        .cloned();
-
-    // Short castling
-    if let Some(rook) = short_rook {
-        if game.color_at(rook.position + (-1, 0)).is_none()
-            && game.color_at(rook.position + (-2, 0)).is_none()
-        {
-            // Squares are not occupied
-            // Make sure the king does not "pass through check"
-
-            if !game.self_check(piece, rook.position + (-1, 0))
-                && !game.self_check(piece, rook.position + (-2, 0))
-            {
-                moves.push(rook.position + (-1, 0));
-            }
+        if position == behind && game.color_at(behind).is_none() {
+            return MoveType::EnPassant;
         }
     }
 
-    // Long castling
-    if let Some(rook) = long_rook {
-        if game.color_at(rook.position + (1, 0)).is_none()
-            && game.color_at(rook.position + (2, 0)).is_none()
-            && game.color_at(rook.position + (3, 0)).is_none()
-        {
-            // Squares are not occupied
-            // Make sure the king does not "pass through check"
-
-            // Here the rook.position + (1, 0) does not matter since the king never passes through it even though the rook does
-            if !game.self_check(piece, rook.position + (2, 0))
-                && !game.self_check(piece, rook.position + (3, 0))
-            {
-                moves.push(rook.position + (2, 0));
+    // Castling (king moved 2 squares, or took its own rook in Chess960 <=> castling)
+    if game.castling_side(piece, position).is_some() {
+        return MoveType::Castling;
+    }
+
+    match game.color_at(position) {
+        Some(color) => {
+            if color != piece.color {
+                return MoveType::Attack;
             }
         }
+        None => return MoveType::Regular,
     }
 
-    moves
+    MoveType::Invalid
 }
@@ -0,0 +1,323 @@
+use crate::{
+    moves::{Move, MoveType},
+    CastlingSide, Game, PieceType, Position,
+};
+
+use std::{fmt, str::FromStr};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SanError {
+    /// The string is not a SAN move at all
+    Invalid(String),
+    /// Well formed, but no legal move in the position matches it
+    Illegal(String),
+    /// More than one legal move matches it
+    Ambiguous(String),
+}
+
+impl fmt::Display for SanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SanError::Invalid(san) => write!(f, "invalid SAN move '{}'", san),
+            SanError::Illegal(san) => write!(f, "illegal move '{}'", san),
+            SanError::Ambiguous(san) => write!(f, "ambiguous move '{}'", san),
+        }
+    }
+}
+
+impl std::error::Error for SanError {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum UciError {
+    /// Not a coordinate move like `e2e4` or `e7e8q`
+    Invalid(String),
+    /// Well formed, but not a legal move in the position
+    Illegal(String),
+}
+
+impl fmt::Display for UciError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UciError::Invalid(uci) => write!(f, "invalid UCI move '{}'", uci),
+            UciError::Illegal(uci) => write!(f, "illegal move '{}'", uci),
+        }
+    }
+}
+
+impl std::error::Error for UciError {}
+
+impl fmt::Display for Move {
+    /// Writes the move the way UCI engines expect it, promotions get a lowercase suffix
+    /// and drops are written like `P@e4`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.move_type == MoveType::Drop {
+            return write!(f, "{}@{}", self.piece.piece_type.to_char(), self.to);
+        }
+        write!(f, "{}{}", self.from, self.to)?;
+        if let Some(promotion) = self.promotion {
+            write!(f, "{}", promotion.to_char().to_ascii_lowercase())?;
+        }
+        Ok(())
+    }
+}
+
+impl Game {
+    /// Parses a move in Standard Algebraic Notation (e.g. `Nbd7`, `exd6 e.p.`, `O-O-O`, `e8=N+`,
+    /// or a drop like `N@f3`) into a legal move in the current position. The returned move
+    /// has no check flags
+    pub fn parse_san(&self, san: &str) -> Result<Move, SanError> {
+        let invalid = || SanError::Invalid(san.to_string());
+
+        // Check, mate, annotations and the optional en passant marker carry no information
+        let mut text = san.trim().trim_end_matches(['+', '#', '!', '?']);
+        text = text.strip_suffix("e.p.").unwrap_or(text).trim_end();
+
+        let candidates = self.legal_moves();
+
+        let matching: Vec<Move> = match text {
+            "O-O" | "0-0" | "O-O-O" | "0-0-0" => {
+                let side = match text.len() {
+                    3 => CastlingSide::Short,
+                    _ => CastlingSide::Long,
+                };
+                candidates
+                    .into_iter()
+                    .filter(|mov| mov.castling_side() == Some(side))
+                    .collect()
+            }
+            // Drops, the piece letter can be left out for pawns
+            _ if text.contains('@') => {
+                let (piece, square) = text.split_once('@').unwrap();
+                let piece_type = match piece {
+                    "" => PieceType::Pawn,
+                    _ if piece.len() == 1 => {
+                        PieceType::from_char(piece.chars().next().unwrap()).ok_or_else(invalid)?
+                    }
+                    _ => return Err(invalid()),
+                };
+                let to = Position::from_str(square).map_err(|_| invalid())?;
+
+                candidates
+                    .into_iter()
+                    .filter(|mov| {
+                        mov.move_type == MoveType::Drop
+                            && mov.piece.piece_type == piece_type
+                            && mov.to == to
+                    })
+                    .collect()
+            }
+            _ => {
+                let mut chars: Vec<char> = text.chars().collect();
+
+                let piece_type = match chars.first() {
+                    Some(&c) if "KQRBN".contains(c) => {
+                        chars.remove(0);
+                        PieceType::from_char(c).unwrap()
+                    }
+                    _ => PieceType::Pawn,
+                };
+
+                // Promotion, both e8=Q and e8Q are seen in the wild
+                let mut promotion = None;
+                if piece_type == PieceType::Pawn
+                    && chars.last().is_some_and(|c| "QRBN".contains(*c))
+                {
+                    promotion = PieceType::from_char(chars.pop().unwrap());
+                    if chars.last() == Some(&'=') {
+                        chars.pop();
+                    }
+                }
+
+                if chars.len() < 2 {
+                    return Err(invalid());
+                }
+                let to_text: String = chars.split_off(chars.len() - 2).into_iter().collect();
+                let to = Position::from_str(&to_text).map_err(|_| invalid())?;
+
+                // Whatever is left is disambiguation and the capture marker
+                if chars.last() == Some(&'x') {
+                    chars.pop();
+                }
+                let mut file = None;
+                let mut rank = None;
+                for c in chars {
+                    match c {
+                        'a'..='h' if file.is_none() && rank.is_none() => {
+                            file = Some(c as i8 - 'a' as i8)
+                        }
+                        '1'..='8' if rank.is_none() => rank = Some(c as i8 - '1' as i8),
+                        _ => return Err(invalid()),
+                    }
+                }
+
+                // Pawns only leave their file when capturing, which is always written with the file
+                if piece_type == PieceType::Pawn && file.is_none() {
+                    file = Some(to.x);
+                }
+
+                candidates
+                    .into_iter()
+                    .filter(|mov| {
+                        mov.move_type != MoveType::Drop
+                            && mov.piece.piece_type == piece_type
+                            && mov.to == to
+                            && mov.promotion == promotion
+                            && file.is_none_or(|x| mov.from.x == x)
+                            && rank.is_none_or(|y| mov.from.y == y)
+                    })
+                    .collect()
+            }
+        };
+
+        match matching.len() {
+            0 => Err(SanError::Illegal(san.to_string())),
+            1 => Ok(matching[0]),
+            _ => Err(SanError::Ambiguous(san.to_string())),
+        }
+    }
+
+    /// Writes a legal move in Standard Algebraic Notation, including check and mate suffixes
+    pub fn to_san(&self, mov: Move) -> Result<String, SanError> {
+        let candidates = self.legal_moves();
+        let mov = *candidates
+            .iter()
+            .find(|candidate| {
+                candidate.from == mov.from
+                    && candidate.to == mov.to
+                    && candidate.promotion == mov.promotion
+                    // Drops of different pieces all go from and to the same square
+                    && (candidate.move_type != MoveType::Drop
+                        || candidate.piece.piece_type == mov.piece.piece_type)
+            })
+            .ok_or_else(|| SanError::Illegal(mov.to_string()))?;
+        // Only the move being written needs to be played out for its suffix
+        let mov = self.with_check_flags(mov);
+
+        let piece = mov.piece;
+        let capture = mov.captured.is_some();
+
+        let mut san = String::new();
+        if mov.move_type == MoveType::Drop {
+            san.push_str(&mov.to_string());
+        } else if let Some(side) = mov.castling_side() {
+            san.push_str(match side {
+                CastlingSide::Short => "O-O",
+                CastlingSide::Long => "O-O-O",
+            });
+        } else if piece.piece_type == PieceType::Pawn {
+            if capture {
+                san.push((b'a' + mov.from.x as u8) as char);
+                san.push('x');
+            }
+            san.push_str(&mov.to.to_string());
+            if let Some(promotion) = mov.promotion {
+                san.push('=');
+                san.push(promotion.to_char());
+            }
+        } else {
+            san.push(piece.piece_type.to_char());
+
+            // Other pieces of the same type that can reach the same square
+            let others: Vec<Position> = candidates
+                .iter()
+                .filter(|other| {
+                    other.move_type != MoveType::Drop
+                        && other.to == mov.to
+                        && other.from != mov.from
+                        && other.piece.piece_type == piece.piece_type
+                })
+                .map(|other| other.from)
+                .collect();
+
+            let file = (b'a' + mov.from.x as u8) as char;
+            let rank = (b'1' + mov.from.y as u8) as char;
+            if !others.is_empty() {
+                if others.iter().all(|other| other.x != mov.from.x) {
+                    san.push(file);
+                } else if others.iter().all(|other| other.y != mov.from.y) {
+                    san.push(rank);
+                } else {
+                    san.push(file);
+                    san.push(rank);
+                }
+            }
+
+            if capture {
+                san.push('x');
+            }
+            san.push_str(&mov.to.to_string());
+        }
+
+        if mov.checkmate {
+            san.push('#');
+        } else if mov.check {
+            san.push('+');
+        }
+
+        Ok(san)
+    }
+
+    /// Parses and plays a SAN move, returning the move that was made
+    pub fn move_san(&mut self, san: &str) -> Result<Move, SanError> {
+        let mov = self.parse_san(san)?;
+        self.make_move(mov);
+        Ok(mov)
+    }
+
+    /// Parses a UCI move like `e2e4`, `e7e8q` or the drop `P@e4` into a legal move in the
+    /// current position. Castling can be given as the king taking its own rook, which
+    /// Chess960 requires. The returned move has no check flags
+    pub fn parse_uci(&self, uci: &str) -> Result<Move, UciError> {
+        let invalid = || UciError::Invalid(uci.to_string());
+        if !uci.is_ascii() || !(4..=5).contains(&uci.len()) {
+            return Err(invalid());
+        }
+
+        if &uci[1..2] == "@" {
+            let piece_type = PieceType::from_char(uci.chars().next().unwrap())
+                .filter(|_| uci.len() == 4)
+                .ok_or_else(invalid)?;
+            let to = Position::from_str(&uci[2..]).map_err(|_| invalid())?;
+            return self
+                .legal_moves()
+                .into_iter()
+                .find(|mov| {
+                    mov.move_type == MoveType::Drop
+                        && mov.piece.piece_type == piece_type
+                        && mov.to == to
+                })
+                .ok_or_else(|| UciError::Illegal(uci.to_string()));
+        }
+
+        let from = Position::from_str(&uci[0..2]).map_err(|_| invalid())?;
+        let to = Position::from_str(&uci[2..4]).map_err(|_| invalid())?;
+        let to = match self.pieces[from.x as usize][from.y as usize] {
+            Some(piece) => self.castling_input(piece, to),
+            None => to,
+        };
+        let promotion = match uci[4..].chars().next() {
+            Some(c) => match PieceType::from_char(c) {
+                Some(PieceType::King) | Some(PieceType::Pawn) | None => return Err(invalid()),
+                piece_type => piece_type,
+            },
+            None => None,
+        };
+
+        self.legal_moves()
+            .into_iter()
+            .find(|mov| {
+                mov.move_type != MoveType::Drop
+                    && mov.from == from
+                    && mov.to == to
+                    && mov.promotion == promotion
+            })
+            .ok_or_else(|| UciError::Illegal(uci.to_string()))
+    }
+
+    /// Parses and plays a UCI move in one step, promotions need their suffix
+    pub fn move_uci(&mut self, uci: &str) -> Result<Move, UciError> {
+        let mov = self.parse_uci(uci)?;
+        self.make_move(mov);
+        Ok(mov)
+    }
+}
@@ -0,0 +1,51 @@
+use crate::{
+    moves::{Move, MoveList},
+    Game,
+};
+
+impl Game {
+    /// Counts the leaf nodes of the legal move tree `depth` plies deep. Comparing against
+    /// known counts is the standard way of finding move generation bugs
+    pub fn perft(&mut self, depth: u32) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+
+        let moves = self.perft_moves();
+        if depth == 1 {
+            return moves.len() as u64;
+        }
+
+        let mut nodes = 0;
+        for mov in moves {
+            self.apply_move(mov);
+            nodes += self.perft(depth - 1);
+            self.unmake_move();
+        }
+        nodes
+    }
+
+    /// Perft split up by the first move, for narrowing down which move a wrong count comes from
+    pub fn divide(&mut self, depth: u32) -> Vec<(Move, u64)> {
+        if depth == 0 {
+            return vec![];
+        }
+
+        self.perft_moves()
+            .into_iter()
+            .map(|mov| {
+                self.apply_move(mov);
+                let nodes = self.perft(depth - 1);
+                self.unmake_move();
+                (mov, nodes)
+            })
+            .collect()
+    }
+
+    /// Legal moves of the player to move without the check flags, which perft doesn't need
+    fn perft_moves(&self) -> MoveList {
+        let mut moves = MoveList::new();
+        self.generate_moves(self.current_move, &mut moves);
+        moves
+    }
+}
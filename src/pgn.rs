@@ -0,0 +1,535 @@
+use crate::{
+    fen::FenError,
+    moves::Move,
+    notation::SanError,
+    variant::{self, Standard, Variant},
+    Color, Game,
+};
+
+use std::fmt;
+
+/// The tags every PGN game is supposed to have, in the order they are exported
+pub const SEVEN_TAG_ROSTER: [&str; 7] =
+    ["Event", "Site", "Date", "Round", "White", "Black", "Result"];
+
+/// A move from PGN movetext along with its annotations
+#[derive(Debug, Clone, PartialEq)]
+pub struct PgnMove {
+    pub san: String,
+    pub mov: Move,
+    /// Numeric annotation glyphs like `$1`, stored without the dollar sign
+    pub nags: Vec<u8>,
+    /// Comments following the move
+    pub comments: Vec<String>,
+}
+
+/// A single game read from a PGN file
+#[derive(Debug, Clone)]
+pub struct PgnGame {
+    /// Tags in the order they appeared
+    pub tags: Vec<(String, String)>,
+    /// Comments before the first move
+    pub comments: Vec<String>,
+    pub moves: Vec<PgnMove>,
+    /// Game termination marker, one of `1-0`, `0-1`, `1/2-1/2` and `*`
+    pub result: String,
+    /// The game with every move replayed
+    pub game: Game,
+}
+
+impl PgnGame {
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PgnErrorKind {
+    InvalidTag,
+    UnterminatedComment,
+    UnterminatedString,
+    UnexpectedToken(String),
+    /// A `)` without a matching `(`
+    UnbalancedVariation,
+    InvalidFen(FenError),
+    IllegalMove(SanError),
+    /// A move after checkmate or anything else that ended the game
+    GameOver,
+}
+
+/// Error while reading PGN, lines and columns start at 1
+#[derive(Debug, Clone, PartialEq)]
+pub struct PgnError {
+    pub line: usize,
+    pub column: usize,
+    pub kind: PgnErrorKind,
+}
+
+impl fmt::Display for PgnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {}: ", self.line, self.column)?;
+        match &self.kind {
+            PgnErrorKind::InvalidTag => write!(f, "invalid tag pair"),
+            PgnErrorKind::UnterminatedComment => write!(f, "unterminated comment"),
+            PgnErrorKind::UnterminatedString => write!(f, "unterminated string"),
+            PgnErrorKind::UnexpectedToken(token) => write!(f, "unexpected '{}'", token),
+            PgnErrorKind::UnbalancedVariation => write!(f, "unbalanced variation"),
+            PgnErrorKind::InvalidFen(error) => write!(f, "{}", error),
+            PgnErrorKind::IllegalMove(error) => write!(f, "{}", error),
+            PgnErrorKind::GameOver => write!(f, "move after the game is over"),
+        }
+    }
+}
+
+impl std::error::Error for PgnError {}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Tag(String, String),
+    Comment(String),
+    Nag(u8),
+    VariationStart,
+    VariationEnd,
+    /// Moves, move numbers and results
+    Symbol(String),
+}
+
+struct Lexer {
+    chars: Vec<char>,
+    index: usize,
+    line: usize,
+    column: usize,
+}
+
+impl Lexer {
+    fn new(text: &str) -> Self {
+        Self {
+            chars: text.chars().collect(),
+            index: 0,
+            line: 1,
+            column: 1,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.index).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.index += 1;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn error(&self, line: usize, column: usize, kind: PgnErrorKind) -> PgnError {
+        PgnError { line, column, kind }
+    }
+
+    /// Returns the next token along with the line and column it started at
+    fn next_token(&mut self) -> Result<Option<(Token, usize, usize)>, PgnError> {
+        // Skip whitespace and escaped lines
+        loop {
+            match self.peek() {
+                Some(c) if c.is_whitespace() => {
+                    self.bump();
+                }
+                Some('%') if self.column == 1 => {
+                    while self.peek().is_some_and(|c| c != '\n') {
+                        self.bump();
+                    }
+                }
+                _ => break,
+            }
+        }
+
+        let (line, column) = (self.line, self.column);
+        let c = match self.bump() {
+            Some(c) => c,
+            None => return Ok(None),
+        };
+
+        let token = match c {
+            '[' => self.read_tag(line, column)?,
+            '{' => {
+                let mut comment = String::new();
+                loop {
+                    match self.bump() {
+                        Some('}') => break,
+                        Some(c) => comment.push(c),
+                        None => {
+                            return Err(self.error(line, column, PgnErrorKind::UnterminatedComment))
+                        }
+                    }
+                }
+                Token::Comment(comment.trim().to_string())
+            }
+            ';' => {
+                let mut comment = String::new();
+                while let Some(c) = self.peek().filter(|c| *c != '\n') {
+                    comment.push(c);
+                    self.bump();
+                }
+                Token::Comment(comment.trim().to_string())
+            }
+            '$' => {
+                let digits = self.read_symbol();
+                let nag = digits.parse().map_err(|_| {
+                    self.error(
+                        line,
+                        column,
+                        PgnErrorKind::UnexpectedToken(format!("${}", digits)),
+                    )
+                })?;
+                Token::Nag(nag)
+            }
+            '(' => Token::VariationStart,
+            ')' => Token::VariationEnd,
+            _ => Token::Symbol(format!("{}{}", c, self.read_symbol())),
+        };
+
+        Ok(Some((token, line, column)))
+    }
+
+    fn read_symbol(&mut self) -> String {
+        let mut symbol = String::new();
+        while let Some(c) = self
+            .peek()
+            .filter(|c| !c.is_whitespace() && !"[]{}();$".contains(*c))
+        {
+            symbol.push(c);
+            self.bump();
+        }
+        symbol
+    }
+
+    fn skip_spaces(&mut self) {
+        while self.peek().is_some_and(|c| c.is_whitespace()) {
+            self.bump();
+        }
+    }
+
+    /// Reads `Name "value"]`, the opening bracket is already consumed
+    fn read_tag(&mut self, line: usize, column: usize) -> Result<Token, PgnError> {
+        let invalid = |lexer: &Self| lexer.error(line, column, PgnErrorKind::InvalidTag);
+
+        self.skip_spaces();
+        let name = self.read_symbol();
+        if name.is_empty() {
+            return Err(invalid(self));
+        }
+
+        self.skip_spaces();
+        if self.bump() != Some('"') {
+            return Err(invalid(self));
+        }
+
+        let mut value = String::new();
+        loop {
+            match self.bump() {
+                Some('"') => break,
+                Some('\\') => match self.bump() {
+                    Some(c) => value.push(c),
+                    None => break,
+                },
+                Some(c) => value.push(c),
+                None => return Err(self.error(line, column, PgnErrorKind::UnterminatedString)),
+            }
+        }
+
+        self.skip_spaces();
+        if self.bump() != Some(']') {
+            return Err(invalid(self));
+        }
+
+        Ok(Token::Tag(name, value))
+    }
+}
+
+/// A game that is still being read
+#[derive(Default)]
+struct GameBuilder {
+    tags: Vec<(String, String)>,
+    /// Where the FEN tag was, for error reporting
+    fen_position: (usize, usize),
+    comments: Vec<String>,
+    moves: Vec<PgnMove>,
+    game: Option<Game>,
+    variation_depth: usize,
+}
+
+impl GameBuilder {
+    fn has_content(&self) -> bool {
+        !self.tags.is_empty() || self.game.is_some()
+    }
+
+    /// The game the moves are played on, set up from the FEN tag on first use
+    fn game(&mut self) -> Result<&mut Game, PgnError> {
+        if self.game.is_none() {
+            let mut game = Game::new();
+            let variant = self.tags.iter().find(|(tag, _)| tag == "Variant");
+            if let Some(variant) = variant.and_then(|(_, name)| variant::from_name(name)) {
+                game.set_variant(variant);
+            }
+            let chess960 = variant.is_some_and(|(_, name)| name.eq_ignore_ascii_case("Chess960"));
+            match self.tags.iter().find(|(tag, _)| tag == "FEN") {
+                Some((_, fen)) => game.load_fen_as(fen, chess960).map_err(|error| PgnError {
+                    line: self.fen_position.0,
+                    column: self.fen_position.1,
+                    kind: PgnErrorKind::InvalidFen(error),
+                })?,
+                None => game.default_board(),
+            }
+            self.game = Some(game);
+        }
+        Ok(self.game.as_mut().unwrap())
+    }
+
+    fn finish(mut self, result: String) -> Result<PgnGame, PgnError> {
+        self.game()?;
+        Ok(PgnGame {
+            tags: self.tags,
+            comments: self.comments,
+            moves: self.moves,
+            result,
+            game: self.game.unwrap(),
+        })
+    }
+}
+
+fn is_result(symbol: &str) -> bool {
+    matches!(symbol, "1-0" | "0-1" | "1/2-1/2" | "*")
+}
+
+/// Strips a leading move number like `12.` or `12...`, which may be glued to the move
+fn strip_move_number(symbol: &str) -> &str {
+    let rest = symbol.trim_start_matches(|c: char| c.is_ascii_digit());
+    if rest.len() < symbol.len() && rest.starts_with('.') || symbol.starts_with('.') {
+        rest.trim_start_matches('.')
+    } else {
+        symbol
+    }
+}
+
+/// Reads every game in a PGN file, replaying the moves so illegal games are rejected
+pub fn parse_pgn(text: &str) -> Result<Vec<PgnGame>, PgnError> {
+    let mut lexer = Lexer::new(text);
+    let mut games = vec![];
+    let mut builder = GameBuilder::default();
+
+    while let Some((token, line, column)) = lexer.next_token()? {
+        let unexpected = |token: String| PgnError {
+            line,
+            column,
+            kind: PgnErrorKind::UnexpectedToken(token),
+        };
+
+        // Everything inside variations is skipped, only the main line is played
+        if builder.variation_depth > 0 {
+            match token {
+                Token::VariationStart => builder.variation_depth += 1,
+                Token::VariationEnd => builder.variation_depth -= 1,
+                _ => (),
+            }
+            continue;
+        }
+
+        match token {
+            Token::Tag(name, value) => {
+                if builder.game.is_some() {
+                    // Movetext without a termination marker, the tag belongs to the next game
+                    let previous = std::mem::take(&mut builder);
+                    games.push(previous.finish("*".to_string())?);
+                }
+                if name == "FEN" {
+                    builder.fen_position = (line, column);
+                }
+                builder.tags.push((name, value));
+            }
+            Token::Comment(comment) => match builder.moves.last_mut() {
+                Some(last) => last.comments.push(comment),
+                None => builder.comments.push(comment),
+            },
+            Token::Nag(nag) => match builder.moves.last_mut() {
+                Some(last) => last.nags.push(nag),
+                None => return Err(unexpected(format!("${}", nag))),
+            },
+            Token::VariationStart => {
+                if builder.moves.is_empty() {
+                    return Err(unexpected("(".to_string()));
+                }
+                builder.variation_depth = 1;
+            }
+            Token::VariationEnd => {
+                return Err(PgnError {
+                    line,
+                    column,
+                    kind: PgnErrorKind::UnbalancedVariation,
+                })
+            }
+            Token::Symbol(symbol) if is_result(&symbol) => {
+                let finished = std::mem::take(&mut builder);
+                games.push(finished.finish(symbol)?);
+            }
+            Token::Symbol(symbol) => {
+                let san = strip_move_number(&symbol);
+                if san.is_empty() || san == "e.p." {
+                    // Just a move number, or an en passant marker written apart from its move
+                    builder.game()?;
+                    continue;
+                }
+
+                let game = builder.game()?;
+                if game.is_over() {
+                    return Err(PgnError {
+                        line,
+                        column,
+                        kind: PgnErrorKind::GameOver,
+                    });
+                }
+                let mov = game.parse_san(san).map_err(|error| PgnError {
+                    line,
+                    column,
+                    kind: PgnErrorKind::IllegalMove(error),
+                })?;
+                game.make_move(mov);
+                game.update_game();
+
+                builder.moves.push(PgnMove {
+                    san: san.to_string(),
+                    mov,
+                    nags: vec![],
+                    comments: vec![],
+                });
+            }
+        }
+    }
+
+    if builder.variation_depth > 0 {
+        return Err(PgnError {
+            line: lexer.line,
+            column: lexer.column,
+            kind: PgnErrorKind::UnbalancedVariation,
+        });
+    }
+
+    // The last game is allowed to leave out its termination marker
+    if builder.has_content() {
+        let result = builder
+            .tags
+            .iter()
+            .find(|(tag, _)| tag == "Result")
+            .map(|(_, result)| result.clone())
+            .unwrap_or("*".to_string());
+        games.push(builder.finish(result)?);
+    }
+
+    Ok(games)
+}
+
+/// Longest line the PGN export format allows
+const MAX_LINE_LENGTH: usize = 79;
+
+impl Game {
+    /// Writes the game's moves as PGN in export format.
+    /// The seven tag roster always comes first, filled with `?` where `tags` doesn't have it,
+    /// followed by the rest of `tags`. The result comes from the board if the game is over,
+    /// otherwise from a `Result` tag, otherwise it is `*`
+    pub fn to_pgn(&self, tags: &[(&str, &str)]) -> String {
+        // Walk back to where the game started
+        let mut game = self.clone();
+        // Replaying shouldn't touch the clock, or run into a flag that fell later
+        game.clock = None;
+        while game.unmake_move().is_some() {}
+
+        let start_fen = game.to_fen();
+        let mut tokens = vec![];
+        for (i, mov) in self.history().into_iter().enumerate() {
+            let san = match game.to_san(mov) {
+                Ok(san) => san,
+                // Only a move still waiting for its promotion can't be written
+                Err(_) => break,
+            };
+
+            if game.current_move == Color::White {
+                tokens.push(format!("{}.", game.fullmove_number));
+            } else if i == 0 {
+                tokens.push(format!("{}...", game.fullmove_number));
+            }
+            tokens.push(san);
+            game.make_move(mov);
+        }
+
+        let tag = |name: &str| {
+            tags.iter()
+                .find(|(tag, _)| *tag == name)
+                .map(|(_, value)| *value)
+        };
+
+        // Resignations and such only exist on the game itself, not in the replayed moves
+        game.update_game();
+        let result = match self.result().or(game.result()) {
+            Some(result) => result.to_pgn(),
+            None => tag("Result").unwrap_or("*"),
+        };
+        tokens.push(result.to_string());
+
+        let mut pgn = String::new();
+        for name in SEVEN_TAG_ROSTER {
+            let value = match name {
+                "Result" => result,
+                "Date" => tag(name).unwrap_or("????.??.??"),
+                _ => tag(name).unwrap_or("?"),
+            };
+            write_tag(&mut pgn, name, value);
+        }
+        if tag("Variant").is_none() {
+            if self.chess960 {
+                write_tag(&mut pgn, "Variant", "Chess960");
+            } else if self.variant.name() != Standard.name() {
+                write_tag(&mut pgn, "Variant", self.variant.name());
+            }
+        }
+        if start_fen != self.variant.starting_fen() {
+            write_tag(&mut pgn, "SetUp", "1");
+            write_tag(&mut pgn, "FEN", &start_fen);
+        }
+        for (name, value) in tags {
+            if !SEVEN_TAG_ROSTER.contains(name) && *name != "SetUp" && *name != "FEN" {
+                write_tag(&mut pgn, name, value);
+            }
+        }
+        pgn.push('\n');
+
+        // Movetext wrapped to the maximum line length
+        let mut line = String::new();
+        for token in tokens {
+            if !line.is_empty() && line.len() + 1 + token.len() > MAX_LINE_LENGTH {
+                pgn.push_str(&line);
+                pgn.push('\n');
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&token);
+        }
+        pgn.push_str(&line);
+        pgn.push('\n');
+
+        pgn
+    }
+}
+
+fn write_tag(pgn: &mut String, name: &str, value: &str) {
+    let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+    pgn.push_str(&format!("[{} \"{}\"]\n", name, value));
+}
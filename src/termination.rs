@@ -0,0 +1,225 @@
+use crate::{Color, Game, GameStatus, PieceType};
+
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Outcome {
+    /// One of the players won
+    Decisive,
+    Draw,
+}
+
+/// Why a game ended
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reason {
+    Checkmate,
+    Resignation,
+    /// A player ran out of time
+    Timeout,
+    /// A player ran out of time, but the opponent couldn't have won anyway
+    TimeoutVsInsufficientMaterial,
+    /// A player left and never came back
+    Abandonment,
+    /// Decided by someone other than the players, like an arbiter
+    Adjudication,
+    Stalemate,
+    /// Both players agreed to a draw
+    Agreement,
+    /// A draw was claimed after the position occurred three times
+    ThreefoldRepetition,
+    /// A draw was claimed after 50 moves without a capture or pawn move
+    FiftyMoveRule,
+    FivefoldRepetition,
+    SeventyFiveMoveRule,
+    InsufficientMaterial,
+    DeadPosition,
+    /// The variant's own rules decided it, like a king reaching the hill
+    VariantEnd,
+}
+
+/// How a game ended, once it has
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GameResult {
+    pub outcome: Outcome,
+    /// None for draws
+    pub winner: Option<Color>,
+    pub reason: Reason,
+}
+
+impl GameResult {
+    pub fn win(winner: Color, reason: Reason) -> Self {
+        Self {
+            outcome: Outcome::Decisive,
+            winner: Some(winner),
+            reason,
+        }
+    }
+
+    pub fn draw(reason: Reason) -> Self {
+        Self {
+            outcome: Outcome::Draw,
+            winner: None,
+            reason,
+        }
+    }
+
+    /// The result for a status that ends the game by itself, None for any other status
+    pub fn from_status(status: GameStatus) -> Option<Self> {
+        let result = match status {
+            GameStatus::Checkmate(checked) => Self::win(!checked, Reason::Checkmate),
+            GameStatus::Stalemate => Self::draw(Reason::Stalemate),
+            GameStatus::SeventyFiveMoveRule => Self::draw(Reason::SeventyFiveMoveRule),
+            GameStatus::InsufficientMaterial => Self::draw(Reason::InsufficientMaterial),
+            GameStatus::DeadPosition => Self::draw(Reason::DeadPosition),
+            GameStatus::FivefoldRepetition => Self::draw(Reason::FivefoldRepetition),
+            _ => return None,
+        };
+        Some(result)
+    }
+
+    /// PGN result token, `1-0`, `0-1` or `1/2-1/2`
+    pub fn to_pgn(&self) -> &'static str {
+        match self.winner {
+            Some(Color::White) => "1-0",
+            Some(Color::Black) => "0-1",
+            None => "1/2-1/2",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TerminationError {
+    GameOver,
+    /// The opponent hasn't offered a draw
+    NoDrawOffer,
+    /// Neither threefold repetition nor the 50 move rule applies
+    NoDrawToClaim,
+}
+
+impl fmt::Display for TerminationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self {
+            TerminationError::GameOver => "the game is already over",
+            TerminationError::NoDrawOffer => "no draw has been offered",
+            TerminationError::NoDrawToClaim => "there is no draw to claim",
+        };
+        write!(f, "{}", reason)
+    }
+}
+
+impl std::error::Error for TerminationError {}
+
+impl Game {
+    /// How the game ended, None while it is still going. A flag that fell counts
+    /// right away, `update_game` doesn't have to be called first
+    pub fn result(&self) -> Option<GameResult> {
+        self.result.or_else(|| {
+            let flagged = self.clock()?.flagged()?;
+            Some(self.timeout_result(flagged))
+        })
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.result().is_some()
+    }
+
+    pub fn resign(&mut self, color: Color) -> Result<GameResult, TerminationError> {
+        self.end(GameResult::win(!color, Reason::Resignation))
+    }
+
+    /// Offers the opponent a draw. The offer stands until it is accepted, declined, or the
+    /// opponent makes a move instead
+    pub fn offer_draw(&mut self, color: Color) -> Result<(), TerminationError> {
+        if self.is_over() {
+            return Err(TerminationError::GameOver);
+        }
+        self.draw_offer = Some(color);
+        Ok(())
+    }
+
+    /// The player with a draw offer standing, if any
+    pub fn draw_offer(&self) -> Option<Color> {
+        self.draw_offer
+    }
+
+    pub fn accept_draw(&mut self, color: Color) -> Result<GameResult, TerminationError> {
+        self.offered_to(color)?;
+        self.end(GameResult::draw(Reason::Agreement))
+    }
+
+    pub fn decline_draw(&mut self, color: Color) -> Result<(), TerminationError> {
+        self.offered_to(color)?;
+        self.draw_offer = None;
+        Ok(())
+    }
+
+    fn offered_to(&self, color: Color) -> Result<(), TerminationError> {
+        if self.is_over() {
+            return Err(TerminationError::GameOver);
+        }
+        match self.draw_offer == Some(!color) {
+            true => Ok(()),
+            false => Err(TerminationError::NoDrawOffer),
+        }
+    }
+
+    /// Ends the game in a draw if the status from `update_game` allows claiming one
+    pub fn claim_draw(&mut self) -> Result<GameResult, TerminationError> {
+        let reason = match self.status {
+            _ if self.is_over() => return Err(TerminationError::GameOver),
+            GameStatus::ThreefoldRepetition => Reason::ThreefoldRepetition,
+            GameStatus::FiftyMoveRule => Reason::FiftyMoveRule,
+            _ => return Err(TerminationError::NoDrawToClaim),
+        };
+        self.end(GameResult::draw(reason))
+    }
+
+    /// Ends the game with a decision from outside, None as the winner is a draw
+    pub fn adjudicate(&mut self, winner: Option<Color>) -> Result<GameResult, TerminationError> {
+        let result = match winner {
+            Some(winner) => GameResult::win(winner, Reason::Adjudication),
+            None => GameResult::draw(Reason::Adjudication),
+        };
+        self.end(result)
+    }
+
+    /// The player left the game and loses it
+    pub fn abandon(&mut self, color: Color) -> Result<GameResult, TerminationError> {
+        self.end(GameResult::win(!color, Reason::Abandonment))
+    }
+
+    /// The player ran out of time and loses it, unless the opponent has nothing left to
+    /// checkmate with
+    pub fn timeout(&mut self, color: Color) -> Result<GameResult, TerminationError> {
+        self.end(self.timeout_result(color))
+    }
+
+    fn timeout_result(&self, color: Color) -> GameResult {
+        match self.can_checkmate(!color) {
+            true => GameResult::win(!color, Reason::Timeout),
+            false => GameResult::draw(Reason::TimeoutVsInsufficientMaterial),
+        }
+    }
+
+    /// Whether a player could still checkmate with the help of the opponent. A lone king
+    /// with an empty pocket never can, and neither can anyone when the material on the
+    /// board is insufficient for the variant
+    pub fn can_checkmate(&self, color: Color) -> bool {
+        let lone_king = self
+            .get_pieces()
+            .iter()
+            .all(|piece| piece.color != color || piece.piece_type == PieceType::King);
+        (!lone_king || !self.pocket(color).is_empty())
+            && !self.variant.is_insufficient_material(self)
+    }
+
+    fn end(&mut self, result: GameResult) -> Result<GameResult, TerminationError> {
+        if self.is_over() {
+            return Err(TerminationError::GameOver);
+        }
+        self.result = Some(result);
+        self.draw_offer = None;
+        self.stop_clock();
+        Ok(result)
+    }
+}
@@ -3,15 +3,16 @@ mod chess_tests {
     use crate::*;
 
     fn elements_eq<T: PartialEq>(left: Vec<T>, right: Vec<T>) -> bool {
-        return left == right
+        left == right
             || (left.iter().all(|elem| right.contains(elem))
-                && right.iter().all(|elem| left.contains(elem)));
+                && right.iter().all(|elem| left.contains(elem)))
     }
 
     #[test]
     fn import_fen() {
         let mut game = Game::new();
-        game.load_fen("rnbqkbnr/pp1ppppp/8/2p5/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2 ");
+        game.load_fen("rnbqkbnr/pp1ppppp/8/2p5/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2 ")
+            .unwrap();
 
         let pieces = [
             Piece {
@@ -211,10 +212,174 @@ mod chess_tests {
         assert!(elements_eq(game.get_pieces(), pieces.into()))
     }
 
+    #[test]
+    fn import_fen_fields() {
+        let mut game = Game::new();
+        game.load_fen("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w Kq f6 0 3")
+            .unwrap();
+
+        assert_eq!(game.current_move, Color::White);
+        assert_eq!(game.halfmove_clock, 0);
+        assert_eq!(game.fullmove_number, 3);
+        assert_eq!(game.en_passant_possible, game.pieces[5][4]);
+        assert!(elements_eq(
+            game.get_valid_moves(game.pieces[4][4].unwrap()),
+            [(4, 5).into(), (5, 5).into()].into()
+        ));
+
+        // Only short castling for white and long castling for black left
+        assert_eq!(
+            game.castling_rights(),
+            CastlingRights {
+                white_short: true,
+                white_long: false,
+                black_short: false,
+                black_long: true,
+            }
+        );
+
+        game.load_fen("4k3/8/8/8/8/8/8/R3K2R b - - 12 40").unwrap();
+        assert_eq!(game.current_move, Color::Black);
+        assert_eq!(game.halfmove_clock, 12);
+        assert_eq!(game.fullmove_number, 40);
+        assert_eq!(game.en_passant_possible, None);
+        assert_eq!(game.castling_rights(), CastlingRights::default());
+
+        // Counters at the very top don't overflow on the next move
+        game.load_fen("4k3/8/8/8/8/8/8/R3K2R b - - 4294967295 4294967295")
+            .unwrap();
+        game.move_san("Kd7").unwrap();
+        assert_eq!(game.halfmove_clock, u32::MAX);
+        assert_eq!(game.fullmove_number, u32::MAX);
+
+        // Pawns loaded off their starting rank can not double step
+        game.load_fen("4k3/8/8/8/8/4P3/8/4K3 w - - 0 1").unwrap();
+        assert!(elements_eq(
+            game.get_valid_moves(game.pieces[4][2].unwrap()),
+            [(4, 3).into()].into()
+        ));
+    }
+
+    #[test]
+    fn export_fen() {
+        let mut game = Game::new();
+        game.default_board();
+        assert_eq!(
+            game.to_fen(),
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"
+        );
+
+        for fen in [
+            "rnbqkbnr/pp1ppppp/8/2p5/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w Kq f6 0 3",
+            "r3k2r/8/8/8/8/8/8/R3K2R w Qk - 7 31",
+            "4k3/8/8/8/8/8/8/4K3 b - - 99 120",
+        ] {
+            game.load_fen(fen).unwrap();
+            assert_eq!(game.to_fen(), fen);
+        }
+
+        game.default_board();
+        game.move_piece((4, 1).into(), (4, 3).into());
+        assert_eq!(
+            game.to_fen(),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"
+        );
+        game.move_piece((6, 7).into(), (5, 5).into());
+        game.move_piece((4, 0).into(), (4, 1).into());
+        assert_eq!(
+            game.to_fen(),
+            "rnbqkb1r/pppppppp/5n2/8/4P3/8/PPPPKPPP/RNBQ1BNR b kq - 2 2"
+        );
+    }
+
+    #[test]
+    fn invalid_fen() {
+        use fen::FenError;
+
+        let mut game = Game::new();
+        game.default_board();
+
+        for (fen, error) in [
+            ("", FenError::Empty),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 extra",
+                FenError::TooManyFields,
+            ),
+            (
+                "rnbqkbnr/ppppxppp/8/8/8/8/PPPPPPPP/RNBQKBNR",
+                FenError::InvalidPiece('x'),
+            ),
+            (
+                "rnbqkbnr/pppppppp/9/8/8/8/PPPPPPPP/RNBQKBNR",
+                FenError::InvalidPiece('9'),
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPP/RNBQKBNR",
+                FenError::InvalidRankLength(6),
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPPP/RNBQKBNR",
+                FenError::InvalidRankLength(6),
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/PPPPPPPP/RNBQKBNR",
+                FenError::InvalidRankCount(7),
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x",
+                FenError::InvalidSideToMove("x".to_string()),
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkx",
+                FenError::InvalidCastling("KQkx".to_string()),
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBN1 w KQkq",
+                FenError::InvalidCastling("KQkq".to_string()),
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e3",
+                FenError::InvalidEnPassant("e3".to_string()),
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e9",
+                FenError::InvalidEnPassant("e9".to_string()),
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - x 1",
+                FenError::InvalidHalfmoveClock("x".to_string()),
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 0",
+                FenError::InvalidFullmoveNumber("0".to_string()),
+            ),
+            ("8/8/8/8/8/8/8/4K3", FenError::MissingKing(Color::Black)),
+            ("k7/8/8/8/8/8/8/4K1K1", FenError::TooManyKings(Color::White)),
+            (
+                "k6P/8/8/8/8/8/8/4K3",
+                FenError::PawnOnBackRank((7, 7).into()),
+            ),
+            (
+                "k7/8/8/8/8/8/8/r3K3 b",
+                FenError::OpponentInCheck(Color::White),
+            ),
+        ] {
+            assert_eq!(game.load_fen(fen), Err(error));
+        }
+
+        // Failed loads leave the game as it was
+        assert_eq!(
+            game.to_fen(),
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"
+        );
+    }
+
     #[test]
     fn pawn_moves() {
         let mut game = Game::new();
-        game.load_fen("rnbqkbnr/pp1ppppp/8/8/4P3/2p2N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2");
+        game.load_fen("rnbqkbnr/pp1ppppp/8/8/4P3/2p2N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2")
+            .unwrap();
         assert!(elements_eq(
             game.get_valid_moves(game.pieces[1][1].unwrap()),
             [(1, 2).into(), (1, 3).into(), (2, 2).into()].into()
@@ -232,7 +397,8 @@ mod chess_tests {
     #[test]
     fn knight_moves() {
         let mut game = Game::new();
-        game.load_fen("rnbqkbnr/pp1ppppp/8/8/4P3/2p2N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2");
+        game.load_fen("rnbqkbnr/pp1ppppp/8/8/4P3/2p2N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2")
+            .unwrap();
 
         assert!(elements_eq(
             game.get_valid_moves(game.pieces[1][0].unwrap()),
@@ -254,7 +420,7 @@ mod chess_tests {
     #[test]
     fn bishop_moves() {
         let mut game = Game::new();
-        game.load_fen("7k/8/8/3p4/8/1B6/2P5/7K");
+        game.load_fen("7k/8/8/3p4/8/1B6/2P5/7K").unwrap();
 
         assert!(elements_eq(
             game.get_valid_moves(game.pieces[1][2].unwrap()),
@@ -265,7 +431,7 @@ mod chess_tests {
     #[test]
     fn rook_moves() {
         let mut game = Game::new();
-        game.load_fen("7k/8/1p6/8/8/1R6/1P6/7K");
+        game.load_fen("7k/8/1p6/8/8/1R6/1P6/7K").unwrap();
 
         assert!(elements_eq(
             game.get_valid_moves(game.pieces[1][2].unwrap()),
@@ -289,7 +455,7 @@ mod chess_tests {
     #[test]
     fn queen_moves() {
         let mut game = Game::new();
-        game.load_fen("7k/1p6/8/3P4/8/1Qr5/8/7K");
+        game.load_fen("7k/1p6/8/3P4/8/1Qr5/8/7K").unwrap();
 
         assert!(elements_eq(
             game.get_valid_moves(game.pieces[1][2].unwrap()),
@@ -315,7 +481,7 @@ mod chess_tests {
     #[test]
     fn king_moves() {
         let mut game = Game::new();
-        game.load_fen("1r6/8/4k3/8/2K5/2P5/8/8");
+        game.load_fen("1r6/8/4k3/8/2K5/2P5/8/8").unwrap();
 
         assert!(elements_eq(
             game.get_valid_moves(game.pieces[2][3].unwrap()),
@@ -327,11 +493,13 @@ mod chess_tests {
     #[test]
     fn check() {
         let mut game = Game::new();
-        game.load_fen("rnb1kbnr/pp1ppppp/8/q1p5/4P3/3P4/PPP2PPP/RNBQKBNR");
+        game.load_fen("rnb1kbnr/pp1ppppp/8/q1p5/4P3/3P4/PPP2PPP/RNBQKBNR")
+            .unwrap();
 
         assert_eq!(game.is_check(), Some(Color::White));
 
-        game.load_fen("rnb1kbnr/pp1ppppp/8/q1p5/4P3/8/PPPP1PPP/RNBQKBNR");
+        game.load_fen("rnb1kbnr/pp1ppppp/8/q1p5/4P3/8/PPPP1PPP/RNBQKBNR")
+            .unwrap();
 
         assert_eq!(game.is_check(), None);
     }
@@ -339,40 +507,43 @@ mod chess_tests {
     #[test]
     fn checkmate() {
         let mut game = Game::new();
-        game.load_fen("rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR");
+        game.load_fen("rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR")
+            .unwrap();
 
         assert_eq!(game.is_checkmate(), Some(Color::White));
 
-        game.load_fen("rnb1kbnr/pppp1ppp/8/4p3/6Pq/4PP2/PPPP3P/RNBQKBNR");
+        game.load_fen("rnb1kbnr/pppp1ppp/8/4p3/6Pq/4PP2/PPPP3P/RNBQKBNR")
+            .unwrap();
 
         assert_eq!(game.is_check(), Some(Color::White));
         assert_eq!(game.is_checkmate(), None);
 
-        game.load_fen("8/8/8/5K1k/8/8/8/7R");
-
-        // This fails because current_move is white and it thinks that white AND black is checkmated so it prioritises white
-        // Easy fix by setting current_move before which will always happen in a real game, however this bug may have
-        // other consequences which are unknown at this time
-        game.current_move = Color::Black;
+        // This used to fail because current_move was white and it thought that white AND black was checkmated so it
+        // prioritised white. Loading the FEN with white to move is now rejected since black is already checked
+        assert_eq!(
+            game.load_fen("8/8/8/5K1k/8/8/8/7R"),
+            Err(fen::FenError::OpponentInCheck(Color::Black))
+        );
+        game.load_fen("8/8/8/5K1k/8/8/8/7R b").unwrap();
         assert_eq!(game.is_checkmate(), Some(Color::Black));
     }
 
     #[test]
     fn stalemate() {
         let mut game = Game::new();
-        game.load_fen("5k2/5P2/5K2/8/8/8/8/8");
+        game.load_fen("5k2/5P2/5K2/8/8/8/8/8").unwrap();
         game.current_move = Color::Black;
         assert!(game.is_stalemate());
         game.current_move = Color::White;
         assert!(!game.is_stalemate());
 
-        game.load_fen("8/8/8/8/8/8/4p1pp/4Kbrk");
+        game.load_fen("8/8/8/8/8/8/4p1pp/4Kbrk").unwrap();
         game.current_move = Color::Black;
         assert!(game.is_stalemate());
         game.current_move = Color::White;
         assert!(!game.is_stalemate());
 
-        game.load_fen("k7/P7/K7/8/5B2/8/8/8");
+        game.load_fen("k7/P7/K7/8/5B2/8/8/8").unwrap();
         game.current_move = Color::Black;
         assert!(game.is_stalemate());
     }
@@ -380,7 +551,7 @@ mod chess_tests {
     #[test]
     fn promotion() {
         let mut game = Game::new();
-        game.load_fen("7k/2P5/8/8/8/8/8/7K");
+        game.load_fen("7k/2P5/8/8/8/8/8/7K").unwrap();
         assert_eq!(game.update_game(), GameStatus::Active);
         game.move_piece((2, 6).into(), (2, 7).into());
         assert_eq!(
@@ -393,7 +564,8 @@ mod chess_tests {
     #[test]
     fn en_passant() {
         let mut game = Game::new();
-        game.load_fen("rnbqkbnr/pppp1ppp/8/8/4p3/8/PPPPPPPP/RNBQKBNR");
+        game.load_fen("rnbqkbnr/pppp1ppp/8/8/4p3/8/PPPPPPPP/RNBQKBNR")
+            .unwrap();
 
         game.move_piece((3, 1).into(), (3, 2).into());
         assert!(elements_eq(
@@ -403,7 +575,8 @@ mod chess_tests {
         game.en_passant_possible = None;
         //println!("{:?}", game.get_valid_moves(game.pieces[4][3].unwrap()));
 
-        game.load_fen("rnbqkbnr/ppp1pppp/3p4/3P4/8/8/PPP1PPPP/RNBQKBNR");
+        game.load_fen("rnbqkbnr/ppp1pppp/3p4/3P4/8/8/PPP1PPPP/RNBQKBNR")
+            .unwrap();
         game.current_move = Color::Black;
         game.move_piece((4, 6).into(), (4, 4).into());
         assert!(elements_eq(
@@ -417,7 +590,7 @@ mod chess_tests {
     #[test]
     fn castling() {
         let mut game = Game::new();
-        game.load_fen("2k5/8/8/3r4/8/8/8/R3K2R");
+        game.load_fen("2k5/8/8/3r4/8/8/8/R3K2R").unwrap();
         assert!(elements_eq(
             game.get_valid_moves(game.pieces[4][0].unwrap()),
             [(4, 1).into(), (5, 1).into(), (5, 0).into(), (6, 0).into()].into()
@@ -425,7 +598,7 @@ mod chess_tests {
         game.move_piece((4, 0).into(), (6, 0).into());
         assert!(game.pieces[5][0].is_some_and(|piece| piece.piece_type == PieceType::Rook));
 
-        game.load_fen("r3k2r/8/8/8/8/8/5R1R/4K3");
+        game.load_fen("r3k2r/8/8/8/8/8/5R1R/4K3").unwrap();
         game.current_move = Color::Black;
         assert!(elements_eq(
             game.get_valid_moves(game.pieces[4][7].unwrap()),
@@ -438,12 +611,1523 @@ mod chess_tests {
         check and so the check check invokes get_pseudo_moves for the opposing king which
         creates infinite recursion if it can castle */
         // Fixed, hope this fix has no other unwanted implications
-        game.load_fen("r3k2r/8/8/8/8/8/4R2R/4K3");
-        game.current_move = Color::Black;
+        game.load_fen("r3k2r/8/8/8/8/8/4R2R/4K3 b").unwrap();
         println!("{:?}", game.get_valid_moves(game.pieces[4][7].unwrap()));
         assert!(elements_eq(
             game.get_valid_moves(game.pieces[4][7].unwrap()),
             [(5, 7).into(), (3, 7).into(), (3, 6).into(), (5, 6).into()].into()
         ));
     }
+
+    #[test]
+    fn castling_rights() {
+        let mut game = Game::new();
+
+        // King and rooks at home but the rights are gone
+        game.load_fen("r3k2r/8/8/8/8/8/8/R3K2R w - - 0 20").unwrap();
+        assert!(elements_eq(
+            game.get_valid_moves(game.pieces[4][0].unwrap()),
+            [
+                (3, 0).into(),
+                (3, 1).into(),
+                (4, 1).into(),
+                (5, 1).into(),
+                (5, 0).into()
+            ]
+            .into()
+        ));
+
+        // Rook captured on its home square
+        game.load_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1")
+            .unwrap();
+        game.move_piece((0, 0).into(), (0, 7).into());
+        assert!(!game.castling_rights().black_long);
+        assert!(!game.castling_rights().white_long);
+        assert!(game.castling_rights().black_short);
+        assert!(!game
+            .get_valid_moves(game.pieces[4][7].unwrap())
+            .contains(&(2, 7).into()));
+
+        // Rooks moving away and back do not bring the rights back
+        game.load_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1")
+            .unwrap();
+        game.move_piece((7, 0).into(), (7, 1).into());
+        game.move_piece((0, 7).into(), (0, 6).into());
+        game.move_piece((7, 1).into(), (7, 0).into());
+        game.move_piece((0, 6).into(), (0, 7).into());
+        assert_eq!(game.to_fen(), "r3k2r/8/8/8/8/8/8/R3K2R w Qk - 4 3");
+
+        // King moving loses both rights
+        game.load_fen("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1")
+            .unwrap();
+        game.move_piece((4, 7).into(), (4, 6).into());
+        assert_eq!(
+            game.castling_rights(),
+            CastlingRights {
+                white_short: true,
+                white_long: true,
+                black_short: false,
+                black_long: false,
+            }
+        );
+    }
+
+    #[test]
+    fn san_parsing() {
+        use notation::SanError;
+
+        let mut game = Game::new();
+        game.load_fen("r3k2r/p1pNqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R b KQkq - 0 1")
+            .unwrap();
+
+        let parse = |game: &Game, san| game.parse_san(san).map(|mov| mov.to_string());
+
+        assert_eq!(parse(&game, "O-O-O"), Ok("e8c8".to_string()));
+        // The knight on d7 covers f8
+        assert_eq!(
+            parse(&game, "0-0"),
+            Err(SanError::Illegal("0-0".to_string()))
+        );
+        assert_eq!(parse(&game, "Nbxd7"), Ok("b6d7".to_string()));
+        assert_eq!(parse(&game, "Nfxd7!?"), Ok("f6d7".to_string()));
+        assert_eq!(parse(&game, "hxg2"), Ok("h3g2".to_string()));
+        assert_eq!(parse(&game, "Bxe2"), Ok("a6e2".to_string()));
+        assert_eq!(
+            parse(&game, "Nxd7"),
+            Err(SanError::Ambiguous("Nxd7".to_string()))
+        );
+        assert_eq!(
+            parse(&game, "Ng5"),
+            Err(SanError::Illegal("Ng5".to_string()))
+        );
+        assert_eq!(
+            parse(&game, "Zz9"),
+            Err(SanError::Invalid("Zz9".to_string()))
+        );
+
+        game.load_fen("4k3/2P5/8/3pP3/8/8/8/4K3 w - d6 0 1")
+            .unwrap();
+        assert_eq!(parse(&game, "exd6 e.p."), Ok("e5d6".to_string()));
+        assert_eq!(parse(&game, "exd6"), Ok("e5d6".to_string()));
+        assert_eq!(parse(&game, "c8=N"), Ok("c7c8n".to_string()));
+        assert_eq!(parse(&game, "c8Q"), Ok("c7c8q".to_string()));
+        assert_eq!(parse(&game, "c8"), Err(SanError::Illegal("c8".to_string())));
+
+        game.move_san("c8=N").unwrap();
+        assert_eq!(
+            game.pieces[2][7].map(|piece| piece.piece_type),
+            Some(PieceType::Knight)
+        );
+    }
+
+    #[test]
+    fn san_generation() {
+        let mut game = Game::new();
+        game.default_board();
+
+        for san in [
+            "e4", "e5", "Nf3", "Nc6", "Bc4", "Nf6", "O-O", "Nxe4", "Re1", "d5",
+        ] {
+            let mov = game.parse_san(san).unwrap();
+            assert_eq!(game.to_san(mov).unwrap(), san);
+            game.move_san(san).unwrap();
+        }
+
+        let mut mov = game.parse_uci("e1e4").unwrap();
+        assert_eq!(game.to_san(mov).unwrap(), "Rxe4");
+        mov.to = (4, 5).into();
+        assert!(game.to_san(mov).is_err());
+
+        game.load_fen("4k3/8/8/8/8/8/8/R3K3 w - - 0 1").unwrap();
+        assert_eq!(
+            game.to_san(game.parse_uci("a1a8").unwrap()).unwrap(),
+            "Ra8+"
+        );
+
+        // Knights on the same file need the rank to tell them apart
+        game.load_fen("4k3/8/8/1N6/8/1N6/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(
+            game.to_san(game.parse_uci("b5d4").unwrap()).unwrap(),
+            "N5d4"
+        );
+
+        // Promotion with mate
+        game.load_fen("7k/4P3/6K1/8/8/8/8/8 w - - 0 1").unwrap();
+        assert_eq!(
+            game.to_san(game.parse_uci("e7e8q").unwrap()).unwrap(),
+            "e8=Q#"
+        );
+        assert_eq!(
+            game.to_san(game.parse_uci("e7e8n").unwrap()).unwrap(),
+            "e8=N"
+        );
+    }
+
+    #[test]
+    fn uci_moves() {
+        use moves::MoveType;
+        use notation::UciError;
+
+        let mut game = Game::new();
+        game.load_fen("4k3/2P5/8/8/8/8/8/4K2R w K - 0 1").unwrap();
+
+        assert_eq!(game.parse_uci("c7c8n").unwrap().to_string(), "c7c8n");
+        assert_eq!(game.parse_uci("C7C8N").unwrap().to_string(), "c7c8n");
+        for invalid in ["", "e2", "e1e", "e1e9", "i1e1", "c7c8k", "c7c8qq", "e1-f1"] {
+            assert_eq!(
+                game.parse_uci(invalid),
+                Err(UciError::Invalid(invalid.to_string()))
+            );
+        }
+
+        assert_eq!(
+            game.move_uci("c7c8"),
+            Err(UciError::Illegal("c7c8".to_string()))
+        );
+        assert_eq!(
+            game.move_uci("e1g1").map(|mov| mov.move_type),
+            Ok(MoveType::Castling)
+        );
+        assert_eq!(
+            game.move_uci("e8d7").map(|mov| mov.move_type),
+            Ok(MoveType::Regular)
+        );
+        assert_eq!(
+            game.move_uci("c7c8r").map(|mov| mov.move_type),
+            Ok(MoveType::Regular)
+        );
+        assert_eq!(game.to_fen(), "2R5/3k4/8/8/8/8/8/5RK1 b - - 0 2");
+    }
+
+    #[test]
+    fn make_move() {
+        use moves::MoveType;
+
+        let mut game = Game::new();
+        game.load_fen("1r2k3/P7/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+
+        let moves = game.get_moves(game.pieces[0][6].unwrap());
+        assert_eq!(moves.len(), 8);
+
+        // Underpromotion by capture in a single step
+        let mov = *moves
+            .iter()
+            .find(|mov| mov.to == (1, 7).into() && mov.promotion == Some(PieceType::Rook))
+            .unwrap();
+        assert_eq!(mov.piece.piece_type, PieceType::Pawn);
+        assert_eq!(
+            mov.captured.map(|piece| piece.piece_type),
+            Some(PieceType::Rook)
+        );
+        assert_eq!(mov.move_type, MoveType::Attack);
+        assert!(mov.check);
+        assert!(!mov.checkmate);
+
+        assert_eq!(game.make_move(mov), MoveType::Attack);
+        assert_eq!(game.update_game(), GameStatus::Check(Color::Black));
+        assert_eq!(
+            game.pieces[1][7].map(|piece| piece.piece_type),
+            Some(PieceType::Rook)
+        );
+
+        // Promotions must say what they promote to, and nothing else can
+        game.load_fen("4k3/P7/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        let mut mov = game.parse_uci("a7a8q").unwrap();
+        mov.promotion = None;
+        assert_eq!(game.make_move(mov), MoveType::Invalid);
+        let mut mov = game.parse_uci("e1e2").unwrap();
+        mov.promotion = Some(PieceType::Queen);
+        assert_eq!(game.make_move(mov), MoveType::Invalid);
+
+        // Replaying moves from another game
+        let mut game = Game::new();
+        game.default_board();
+        let mut replay = Game::new();
+        replay.default_board();
+        for san in ["e4", "d5", "exd5", "Qxd5", "Nc3", "Qa5"] {
+            let mov = game.move_san(san).unwrap();
+            assert_ne!(replay.make_move(mov), MoveType::Invalid);
+        }
+        assert_eq!(game.to_fen(), replay.to_fen());
+    }
+
+    #[test]
+    fn undo() {
+        let mut game = Game::new();
+        game.load_fen("r3k2r/1pp3P1/8/3pP3/8/8/8/R3K2R w KQkq d6 5 20")
+            .unwrap();
+        let start_fen = game.to_fen();
+        let start_pieces = game.pieces;
+
+        // En passant, castling both ways, captures and a capture promotion
+        for uci in ["e5d6", "e8c8", "e1g1", "c7d6", "g7h8q", "d8h8", "f1f7"] {
+            game.move_uci(uci).unwrap();
+        }
+        assert_eq!(game.history().len(), 7);
+
+        while game.undo().is_some() {}
+
+        assert_eq!(game.to_fen(), start_fen);
+        assert_eq!(game.pieces, start_pieces);
+        assert!(game.history().is_empty());
+        assert_eq!(game.unmake_move(), None);
+
+        // Taking back a move that is waiting for its promotion
+        game.load_fen("4k3/P7/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        game.move_piece((0, 6).into(), (0, 7).into());
+        assert!(matches!(game.update_game(), GameStatus::Promotion(_)));
+        assert_eq!(
+            game.undo().map(|mov| mov.to_string()),
+            Some("a7a8".to_string())
+        );
+        assert_eq!(game.update_game(), GameStatus::Active);
+        assert_eq!(game.to_fen(), "4k3/P7/8/8/8/8/8/4K3 w - - 0 1");
+
+        // Promoting afterwards is remembered in the history
+        game.move_piece((0, 6).into(), (0, 7).into());
+        game.promote(PieceType::Knight);
+        assert_eq!(game.history()[0].to_string(), "a7a8n");
+    }
+
+    #[test]
+    fn pgn_import() {
+        use pgn::{parse_pgn, PgnErrorKind};
+
+        let text = r#"[Event "F/S Return Match"]
+[Site "Belgrade, Serbia JUG"]
+[Date "1992.11.04"]
+[Round "29"]
+[White "Fischer, Robert J."]
+[Black "Spassky, Boris V."]
+[Result "1/2-1/2"]
+[Annotator "Someone \"quoted\""]
+
+{Opening comment} 1. e4 e5 2. Nf3 Nc6 3. Bb5 {This opening is called the Ruy Lopez.}
+3... a6 $1 (3... Nf6 4. O-O (4. d3) Nxe4) 4. Ba4 Nf6 5. O-O Be7 ; rest of line
+% escaped line 1. d4
+6.Re1 b5 7. Bb3 d6 8. c3 O-O 9. h3 Nb8 10. d4 Nbd7 1/2-1/2
+
+[Event "Short"]
+[SetUp "1"]
+[FEN "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1"]
+
+1. exd6 e.p. Kd7 *
+
+1. f3 e5 2. g4 Qh4# 0-1
+"#;
+
+        let mut games = parse_pgn(text).unwrap();
+        assert_eq!(games.len(), 3);
+
+        let game = &games[0];
+        assert_eq!(game.tag("White"), Some("Fischer, Robert J."));
+        assert_eq!(game.tag("Annotator"), Some("Someone \"quoted\""));
+        assert_eq!(game.tags.len(), 8);
+        assert_eq!(game.result, "1/2-1/2");
+        assert_eq!(game.comments, ["Opening comment"]);
+        assert_eq!(game.moves.len(), 20);
+        assert_eq!(
+            game.moves[4].comments,
+            ["This opening is called the Ruy Lopez."]
+        );
+        assert_eq!(game.moves[5].nags, [1]);
+        assert_eq!(game.moves[9].comments, ["rest of line"]);
+        assert_eq!(game.moves[19].san, "Nbd7");
+        assert_eq!(
+            game.game.to_fen(),
+            "r1bq1rk1/2pnbppp/p2p1n2/1p2p3/3PP3/1BP2N1P/PP3PP1/RNBQR1K1 w - - 1 11"
+        );
+
+        assert_eq!(games[1].result, "*");
+        assert_eq!(games[1].moves.len(), 2);
+        assert_eq!(games[1].game.to_fen(), "8/3k4/3P4/8/8/8/8/4K3 w - - 1 2");
+
+        assert!(games[2].tags.is_empty());
+        assert_eq!(games[2].result, "0-1");
+        assert_eq!(
+            games[2].game.update_game(),
+            GameStatus::Checkmate(Color::White)
+        );
+
+        // Illegal moves are reported where they are
+        let error = parse_pgn("[Event \"?\"]\n\n1. e4 e5\n2. Ke3 *").unwrap_err();
+        assert_eq!((error.line, error.column), (4, 4));
+        assert!(matches!(error.kind, PgnErrorKind::IllegalMove(_)));
+
+        let error = parse_pgn("1. e4 {never closed").unwrap_err();
+        assert_eq!((error.line, error.column), (1, 7));
+        assert_eq!(error.kind, PgnErrorKind::UnterminatedComment);
+
+        let error = parse_pgn("[FEN \"8/8/8/8/8/8/8/8\"]\n1. e4 *").unwrap_err();
+        assert_eq!((error.line, error.column), (1, 1));
+        assert!(matches!(error.kind, PgnErrorKind::InvalidFen(_)));
+
+        // Nothing can be played once the game is decided
+        let error = parse_pgn("1. f3 e5 2. g4 Qh4# 3. a3 *").unwrap_err();
+        assert_eq!((error.line, error.column), (1, 24));
+        assert_eq!(error.kind, PgnErrorKind::GameOver);
+        let error = parse_pgn(
+            "[Variant \"King of the Hill\"]\n[FEN \"4k3/8/8/8/8/4K3/8/8 w - - 0 1\"]\n\n1. Ke4 Kd7 *",
+        )
+        .unwrap_err();
+        assert_eq!((error.line, error.column), (4, 8));
+        assert_eq!(error.kind, PgnErrorKind::GameOver);
+    }
+
+    #[test]
+    fn pgn_export() {
+        use pgn::parse_pgn;
+
+        let mut game = Game::new();
+        game.default_board();
+        for san in ["e4", "e5", "Qh5", "Nc6", "Bc4", "Nf6", "Qxf7#"] {
+            game.move_san(san).unwrap();
+        }
+        assert_eq!(
+            game.to_pgn(&[
+                ("White", "Me"),
+                ("Black", "Someone \"else\""),
+                ("Result", "0-1"),
+                ("Opening", "Scholar's mate")
+            ]),
+            r#"[Event "?"]
+[Site "?"]
+[Date "????.??.??"]
+[Round "?"]
+[White "Me"]
+[Black "Someone \"else\""]
+[Result "1-0"]
+[Opening "Scholar's mate"]
+
+1. e4 e5 2. Qh5 Nc6 3. Bc4 Nf6 4. Qxf7# 1-0
+"#
+        );
+
+        // Games from a position start with the FEN and black moves get their own number
+        game.load_fen("4k3/8/8/8/8/8/4p3/4K3 b - - 0 30").unwrap();
+        game.move_san("Kd7").unwrap();
+        game.move_san("Kxe2").unwrap();
+        let pgn = game.to_pgn(&[("Result", "1/2-1/2")]);
+        assert!(pgn.contains("[SetUp \"1\"]\n[FEN \"4k3/8/8/8/8/8/4p3/4K3 b - - 0 30\"]\n"));
+        assert!(pgn.ends_with("\n\n30... Kd7 31. Kxe2 1/2-1/2\n"));
+
+        // Long games are wrapped and read back the same
+        let games = parse_pgn(
+            "1. e4 e5 2. Nf3 Nc6 3. Bb5 a6 4. Ba4 Nf6 5. O-O Be7 6. Re1 b5 7. Bb3 d6 8. c3 O-O
+            9. h3 Nb8 10. d4 Nbd7 11. c4 c6 12. cxb5 axb5 13. Nc3 Bb7 14. Bg5 b4 *",
+        )
+        .unwrap();
+        let pgn = games[0].game.to_pgn(&[]);
+        assert!(pgn.lines().all(|line| line.len() <= 79));
+        let again = parse_pgn(&pgn).unwrap();
+        assert_eq!(again[0].game.to_fen(), games[0].game.to_fen());
+        assert_eq!(again[0].tag("Result"), Some("*"));
+    }
+
+    #[test]
+    fn repetition() {
+        let mut game = Game::new();
+        game.default_board();
+
+        let shuffle = ["Nf3", "Nf6", "Ng1", "Ng8"];
+        for (i, san) in shuffle.iter().cycle().take(16).enumerate() {
+            game.move_san(san).unwrap();
+            let status = game.update_game();
+            match i {
+                7 | 11 => assert_eq!(status, GameStatus::ThreefoldRepetition),
+                15 => assert_eq!(status, GameStatus::FivefoldRepetition),
+                _ => assert_ne!(status, GameStatus::FivefoldRepetition),
+            }
+        }
+        assert_eq!(game.repetition_count(), 5);
+
+        // Castling rights are part of the position
+        game.load_fen("r3k3/8/8/8/8/8/8/4K2R w Kq - 0 1").unwrap();
+        for uci in ["h1h2", "a8a7", "h2h1", "a7a8"] {
+            game.move_uci(uci).unwrap();
+        }
+        assert_eq!(game.repetition_count(), 1);
+        for uci in ["h1h2", "a8a7", "h2h1", "a7a8"] {
+            game.move_uci(uci).unwrap();
+        }
+        assert_eq!(game.repetition_count(), 2);
+
+        // En passant only counts if a pawn can actually take
+        game.load_fen("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1").unwrap();
+        for uci in ["e2e4", "e8d8", "e1d1", "d8e8", "d1e1"] {
+            game.move_uci(uci).unwrap();
+        }
+        assert_eq!(game.repetition_count(), 2);
+        game.load_fen("4k3/8/8/8/5p2/8/4P3/4K3 w - - 0 1").unwrap();
+        for uci in ["e2e4", "e8d8", "e1d1", "d8e8", "d1e1"] {
+            game.move_uci(uci).unwrap();
+        }
+        assert_eq!(game.repetition_count(), 1);
+        game.move_uci("e8d8").unwrap();
+        game.move_uci("e1d1").unwrap();
+        game.move_uci("d8e8").unwrap();
+        game.move_uci("d1e1").unwrap();
+        assert_eq!(game.repetition_count(), 2);
+    }
+
+    #[test]
+    fn move_rules() {
+        let mut game = Game::new();
+        game.load_fen("4k3/8/8/8/8/8/4P3/R3K3 w - - 98 60").unwrap();
+
+        // Pawn moves and captures reset the clock
+        game.move_uci("e2e3").unwrap();
+        assert_eq!(game.halfmove_clock, 0);
+        game.load_fen("r3k3/8/8/8/8/8/8/R3K3 w - - 98 60").unwrap();
+        game.move_uci("a1a8").unwrap();
+        assert_eq!(game.halfmove_clock, 0);
+
+        game.load_fen("4k3/8/8/8/8/8/8/R3K3 w - - 98 60").unwrap();
+        game.move_uci("a1a2").unwrap();
+        assert_eq!(game.update_game(), GameStatus::Active);
+        game.move_uci("e8d8").unwrap();
+        assert_eq!(game.halfmove_clock, 100);
+        assert_eq!(game.update_game(), GameStatus::FiftyMoveRule);
+
+        // Checks still get reported while a draw can be claimed
+        game.move_uci("a2d2").unwrap();
+        assert_eq!(game.update_game(), GameStatus::Check(Color::Black));
+
+        game.load_fen("4k3/8/8/8/8/8/8/R3K3 w - - 149 100").unwrap();
+        game.move_uci("a1a2").unwrap();
+        assert_eq!(game.update_game(), GameStatus::SeventyFiveMoveRule);
+
+        // Mate on the last move still counts
+        game.load_fen("4k3/R7/8/8/8/8/8/1R2K3 w - - 149 100")
+            .unwrap();
+        game.move_uci("b1b8").unwrap();
+        assert_eq!(game.update_game(), GameStatus::Checkmate(Color::Black));
+    }
+
+    #[test]
+    fn insufficient_material() {
+        let mut game = Game::new();
+
+        for (fen, insufficient) in [
+            ("4k3/8/8/8/8/8/8/4K3", true),
+            ("4k3/8/8/8/8/8/8/2B1K3", true),
+            ("4k3/8/8/8/8/8/8/1N2K3", true),
+            ("4k3/8/8/8/5b2/8/8/2B1K3", true),
+            ("2b1k3/8/8/8/8/8/8/2B1K3", false),
+            ("4k3/8/8/8/8/8/8/1NN1K3", false),
+            ("4k3/8/8/8/8/8/8/1n2K1B1", false),
+            ("4k3/8/8/8/8/8/7P/4K3", false),
+            ("4k3/8/8/8/8/8/8/R3K3", false),
+        ] {
+            game.load_fen(fen).unwrap();
+            assert_eq!(game.is_insufficient_material(), insufficient, "{}", fen);
+        }
+
+        game.load_fen("4k3/8/8/8/8/1n6/8/Q3K3 b - - 0 1").unwrap();
+        game.move_uci("b3a1").unwrap();
+        assert_eq!(game.update_game(), GameStatus::InsufficientMaterial);
+    }
+
+    #[test]
+    fn dead_position() {
+        let mut game = Game::new();
+
+        for (fen, dead) in [
+            // Locked pawn chain across the whole board
+            ("8/4k3/8/p1p1p1p1/P1P1P1P1/8/4K3/8", true),
+            ("8/4k3/8/1p1p1p1p/pPpPpPpP/P1P1P1P1/4K3/8", true),
+            // The king can walk around and take something
+            ("8/4k3/8/p3p3/P3P3/8/4K3/8", false),
+            // A pawn can still move or capture
+            ("8/4k3/8/p1p1p1p1/P1P1P1P1/7P/4K3/8", false),
+            ("8/4k3/8/p1p1p1p1/PPP1P1P1/8/4K3/8", false),
+            // Other pieces can always do something
+            ("8/4k3/8/p1p1p1p1/P1P1P1P1/8/4K3/6N1", false),
+            ("4k3/8/8/8/8/8/8/4K3", false),
+        ] {
+            game.load_fen(fen).unwrap();
+            assert_eq!(game.is_dead_position(), dead, "{}", fen);
+        }
+
+        game.load_fen("8/4k3/8/p1p1p1p1/P1P1P1P1/8/4K3/8 w - - 0 1")
+            .unwrap();
+        assert_eq!(game.update_game(), GameStatus::DeadPosition);
+    }
+
+    #[test]
+    fn zobrist() {
+        use std::collections::HashSet;
+
+        let mut game = Game::new();
+        game.default_board();
+        let start = game.zobrist_key();
+
+        // Castling, en passant, captures and promotion all keep the key in sync
+        let moves = [
+            "e4", "d5", "exd5", "c5", "dxc6", "Nf6", "cxb7", "Be6", "bxa8=Q", "Nbd7", "Nf3", "g6",
+            "Bb5", "Bg7", "O-O", "O-O",
+        ];
+        for san in moves {
+            game.move_san(san).unwrap();
+            let mut fresh = Game::new();
+            fresh.load_fen(&game.to_fen()).unwrap();
+            assert_eq!(game.zobrist_key(), fresh.zobrist_key(), "after {}", san);
+        }
+        while game.undo().is_some() {}
+        assert_eq!(game.zobrist_key(), start);
+
+        // Keys match the ones from the Polyglot book format
+        assert_eq!(start, 0x463b96181691fc9c);
+        game.move_san("e4").unwrap();
+        assert_eq!(game.zobrist_key(), 0x823c9b50fd114196);
+        game.move_san("d5").unwrap();
+        assert_eq!(game.zobrist_key(), 0x0756b94461c50fb0);
+        game.undo();
+        game.undo();
+
+        // Pending promotions too
+        game.load_fen("8/1P2k3/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        game.move_piece("b7".parse().unwrap(), "b8".parse().unwrap());
+        game.promote(PieceType::Knight);
+        let mut fresh = Game::new();
+        fresh.load_fen(&game.to_fen()).unwrap();
+        assert_eq!(game.zobrist_key(), fresh.zobrist_key());
+
+        // Transpositions are the same position, move counters don't matter
+        let mut a = Game::new();
+        a.default_board();
+        let mut b = a.clone();
+        for san in ["Nf3", "Nf6", "d4"] {
+            a.move_san(san).unwrap();
+        }
+        for san in ["d4", "Nf6", "Nf3"] {
+            b.move_san(san).unwrap();
+        }
+        assert_eq!(a, b);
+        assert_eq!(a.zobrist_key(), b.zobrist_key());
+        assert_eq!(HashSet::from([a.clone(), b.clone()]).len(), 1);
+        a.move_san("e6").unwrap();
+        assert_ne!(a.zobrist_key(), start);
+
+        // En passant only changes the key when a pawn can take
+        a.load_fen("4k3/8/8/8/4P3/8/8/4K3 b - e3 0 1").unwrap();
+        b.load_fen("4k3/8/8/8/4P3/8/8/4K3 b - - 0 1").unwrap();
+        assert_eq!(a, b);
+        a.load_fen("4k3/8/8/8/4Pp2/8/8/4K3 b - e3 0 1").unwrap();
+        b.load_fen("4k3/8/8/8/4Pp2/8/8/4K3 b - - 0 1").unwrap();
+        assert_ne!(a.zobrist_key(), b.zobrist_key());
+        assert_ne!(a, b);
+
+        // The same board under other rules is another position
+        let mut c = b.clone();
+        c.set_variant(&variant::KingOfTheHill);
+        assert_ne!(b, c);
+    }
+
+    #[test]
+    fn perft() {
+        // Known counts from the chess programming wiki, deep enough to hit castling,
+        // en passant and promotions while keeping the tests quick
+        let positions: [(&str, &[u64]); 6] = [
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                &[20, 400, 8902, 197281],
+            ),
+            // Kiwipete
+            (
+                "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                &[48, 2039, 97862],
+            ),
+            (
+                "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+                &[14, 191, 2812, 43238, 674624],
+            ),
+            (
+                "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+                &[6, 264, 9467],
+            ),
+            (
+                "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+                &[44, 1486, 62379],
+            ),
+            (
+                "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+                &[46, 2079, 89890],
+            ),
+        ];
+
+        let mut game = Game::new();
+        for (fen, counts) in positions {
+            game.load_fen(fen).unwrap();
+            let key = game.zobrist_key();
+            for (depth, count) in counts.iter().enumerate() {
+                assert_eq!(
+                    game.perft(depth as u32 + 1),
+                    *count,
+                    "{} depth {}",
+                    fen,
+                    depth + 1
+                );
+            }
+            // Everything is taken back again
+            assert_eq!(game.to_fen(), fen);
+            assert_eq!(game.zobrist_key(), key);
+            assert!(game.history().is_empty());
+        }
+
+        assert_eq!(game.perft(0), 1);
+
+        // The en passant capture that uncovers a rook on the king is not allowed
+        game.load_fen("8/2p5/3p4/KP5r/1R2Pp1k/8/6P1/8 b - e3 0 1")
+            .unwrap();
+        let divide = game.divide(2);
+        assert_eq!(divide.len(), 16);
+        assert!(!divide.iter().any(|(mov, _)| mov.to_string() == "f4e3"));
+        assert_eq!(
+            divide.iter().map(|(_, nodes)| nodes).sum::<u64>(),
+            game.perft(2)
+        );
+    }
+
+    #[test]
+    fn bitboards() {
+        use bitboard::*;
+
+        let at = |text: &str| square(Position::from_str(text).unwrap());
+        let set = |squares: &[&str]| {
+            squares
+                .iter()
+                .fold(0, |board, square| board | 1 << at(square))
+        };
+
+        assert_eq!(KNIGHT_ATTACKS[at("a1")], set(&["b3", "c2"]));
+        assert_eq!(KING_ATTACKS[at("h8")], set(&["g8", "g7", "h7"]));
+        assert_eq!(PAWN_ATTACKS[Color::Black as usize][at("a5")], set(&["b4"]));
+        assert_eq!(
+            rook_attacks(at("d4"), set(&["d6", "b4", "d1"])),
+            set(&["d5", "d6", "c4", "b4", "e4", "f4", "g4", "h4", "d3", "d2", "d1"])
+        );
+        assert_eq!(bishop_attacks(at("a1"), set(&["c3"])), set(&["b2", "c3"]));
+
+        // The bitboards follow the board through moves and takebacks
+        let mut game = Game::new();
+        game.load_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1")
+            .unwrap();
+        for uci in ["a2a4", "b4a3", "e1g1", "a3b2", "e5f7", "b2a1q", "f7h8"] {
+            game.move_uci(uci).unwrap();
+            assert_eq!(*game.bitboards(), Bitboards::from_board(&game.pieces));
+        }
+        while game.undo().is_some() {
+            assert_eq!(*game.bitboards(), Bitboards::from_board(&game.pieces));
+        }
+        assert_eq!(
+            game.bitboards().pieces(Color::White, PieceType::Pawn),
+            set(&["d5", "e4", "a2", "b2", "c2", "f2", "g2", "h2"])
+        );
+    }
+
+    #[test]
+    fn square_attacks() {
+        let mut game = Game::new();
+        game.load_fen("4k3/8/8/3p4/8/1N3B2/8/R3K2r w Q - 0 1")
+            .unwrap();
+        let at = |square: &str| Position::from_str(square).unwrap();
+
+        // Pawns only attack forwards
+        assert!(game.is_square_attacked(at("c4"), Color::Black));
+        assert!(!game.is_square_attacked(at("d4"), Color::Black));
+        assert!(!game.is_square_attacked(at("c6"), Color::Black));
+
+        // Pieces in the way block sliders, including the piece on the square itself
+        assert!(game.is_square_attacked(at("d5"), Color::White));
+        assert!(!game.is_square_attacked(at("e6"), Color::White));
+        assert!(game.is_square_attacked(at("f1"), Color::Black));
+        assert!(!game.is_square_attacked(at("d1"), Color::Black));
+
+        let mut attackers: Vec<String> = game
+            .attackers_of(at("e4"))
+            .iter()
+            .map(|piece| piece.position.to_string())
+            .collect();
+        attackers.sort();
+        assert_eq!(attackers, ["d5", "f3"]);
+        // Both colors, the a1 rook defends its king
+        assert_eq!(game.attackers_of(at("e1")).len(), 2);
+        assert!(game.is_check() == Some(Color::White));
+
+        // The king can't step back along the rook's ray, and can't castle out of check
+        let king = game.pieces[4][0].unwrap();
+        assert!(!game.get_valid_moves(king).contains(&at("d1")));
+        assert!(!game.get_valid_moves(king).contains(&at("c1")));
+        assert!(game.get_valid_moves(king).contains(&at("d2")));
+
+        // Castling through an attacked square
+        game.load_fen("r3k2r/8/8/8/8/8/8/3K4 b kq - 0 1").unwrap();
+        let king = game.pieces[4][7].unwrap();
+        let moves = game.get_valid_moves(king);
+        assert!(moves.contains(&at("c8")) && moves.contains(&at("g8")));
+        game.load_fen("r3k2r/8/8/8/8/8/8/3K1R2 b kq - 0 1").unwrap();
+        let moves = game.get_valid_moves(game.pieces[4][7].unwrap());
+        assert!(moves.contains(&at("c8")) && !moves.contains(&at("g8")));
+    }
+
+    #[test]
+    fn pins_and_evasions() {
+        let mut game = Game::new();
+        let at = |square: &str| Position::from_str(square).unwrap();
+        let moves_from = |game: &Game, square: &str| {
+            let mut moves: Vec<String> = game
+                .get_valid_moves(game.pieces[at(square).x as usize][at(square).y as usize].unwrap())
+                .iter()
+                .map(|position| position.to_string())
+                .collect();
+            moves.sort();
+            moves
+        };
+
+        // Pinned pieces can only move along the pin
+        game.load_fen("4r2k/8/8/8/8/4N3/8/r2RK3 w - - 0 1").unwrap();
+        assert_eq!(moves_from(&game, "e3"), Vec::<String>::new());
+        assert_eq!(moves_from(&game, "d1"), ["a1", "b1", "c1"]);
+
+        // In check, pieces can only block or take the checker
+        game.load_fen("4r1k1/8/8/8/8/3N4/1B6/R3K3 w - - 0 1")
+            .unwrap();
+        assert_eq!(moves_from(&game, "d3"), ["e5"]);
+        assert_eq!(moves_from(&game, "a1"), Vec::<String>::new());
+        assert_eq!(moves_from(&game, "b2"), ["e5"]);
+
+        // Double check, only the king moves
+        game.load_fen("4r1k1/8/8/8/8/3n4/1B6/R3K3 w - - 0 1")
+            .unwrap();
+        assert!(moves_from(&game, "b2").is_empty());
+        assert_eq!(moves_from(&game, "e1"), ["d1", "d2", "f1"]);
+
+        // Taking the checking pawn en passant
+        game.load_fen("8/8/8/2k5/3Pp3/8/8/4K3 b - d3 0 1").unwrap();
+        assert_eq!(moves_from(&game, "e4"), ["d3"]);
+    }
+
+    #[test]
+    fn legal_moves() {
+        let mut game = Game::new();
+        game.default_board();
+        assert_eq!(game.legal_moves().len(), 20);
+        assert_eq!(game.legal_moves_from("g1".parse().unwrap()).len(), 2);
+        // Not white's piece, or nothing there
+        assert!(game.legal_moves_from("g8".parse().unwrap()).is_empty());
+        assert!(game.legal_moves_from("e4".parse().unwrap()).is_empty());
+
+        // Every promotion choice is its own move
+        game.load_fen("3n3k/2P5/8/8/8/8/8/K7 w - - 0 1").unwrap();
+        let moves = game.legal_moves_from("c7".parse().unwrap());
+        assert_eq!(moves.len(), 8);
+        for promotion in moves::PROMOTIONS {
+            assert_eq!(
+                moves
+                    .iter()
+                    .filter(|mov| mov.promotion == Some(promotion))
+                    .count(),
+                2
+            );
+        }
+        assert_eq!(game.legal_moves().len(), 11);
+
+        // Nothing can be played while a promotion is waiting
+        game.move_piece("c7".parse().unwrap(), "c8".parse().unwrap());
+        assert!(game.legal_moves().is_empty());
+        game.promote(PieceType::Queen);
+        assert!(!game.legal_moves().is_empty());
+    }
+
+    #[test]
+    fn illegal_moves() {
+        use moves::IllegalMove;
+
+        let mut game = Game::new();
+        game.default_board();
+        let try_move = |game: &mut Game, from: &str, to: &str| {
+            game.try_move(from.parse().unwrap(), to.parse().unwrap(), None)
+        };
+
+        assert_eq!(try_move(&mut game, "e4", "e5"), Err(IllegalMove::NoPiece));
+        assert_eq!(try_move(&mut game, "e7", "e5"), Err(IllegalMove::WrongSide));
+        assert_eq!(
+            try_move(&mut game, "e2", "d3"),
+            Err(IllegalMove::InvalidMovement)
+        );
+        assert_eq!(
+            try_move(&mut game, "e2", "e5"),
+            Err(IllegalMove::InvalidMovement)
+        );
+        assert_eq!(
+            try_move(&mut game, "f1", "a6"),
+            Err(IllegalMove::PathBlocked)
+        );
+        assert_eq!(
+            try_move(&mut game, "d1", "d2"),
+            Err(IllegalMove::PathBlocked)
+        );
+        assert_eq!(
+            try_move(&mut game, "g1", "g3"),
+            Err(IllegalMove::InvalidMovement)
+        );
+        assert_eq!(
+            try_move(&mut game, "e1", "g1"),
+            Err(IllegalMove::PathBlocked)
+        );
+
+        let mov = try_move(&mut game, "e2", "e4").unwrap();
+        assert_eq!(mov.move_type, MoveType::Regular);
+        assert!(!mov.check);
+
+        // Pins and checks
+        game.load_fen("k2rr3/8/8/8/8/4N3/8/4K2R w K - 0 1").unwrap();
+        assert_eq!(
+            try_move(&mut game, "e3", "g4"),
+            Err(IllegalMove::LeavesKingInCheck)
+        );
+        assert_eq!(
+            try_move(&mut game, "e1", "d2"),
+            Err(IllegalMove::LeavesKingInCheck)
+        );
+        game.load_fen("k4r2/8/8/8/8/8/8/4K2R w K - 0 1").unwrap();
+        assert_eq!(
+            try_move(&mut game, "e1", "g1"),
+            Err(IllegalMove::CastlingThroughCheck)
+        );
+        assert_eq!(
+            try_move(&mut game, "e1", "c1"),
+            Err(IllegalMove::CastlingRightsLost)
+        );
+        game.load_fen("k5r1/8/8/8/8/8/8/4K2R w K - 0 1").unwrap();
+        assert_eq!(
+            try_move(&mut game, "e1", "g1"),
+            Err(IllegalMove::LeavesKingInCheck)
+        );
+        game.load_fen("k7/8/8/8/8/8/8/4K2R w - - 0 1").unwrap();
+        assert_eq!(
+            try_move(&mut game, "e1", "g1"),
+            Err(IllegalMove::CastlingRightsLost)
+        );
+
+        // Promotions
+        game.load_fen("7k/2P5/8/8/8/8/8/K7 w - - 0 1").unwrap();
+        let (c7, c8) = ("c7".parse().unwrap(), "c8".parse().unwrap());
+        assert_eq!(
+            game.try_move(c7, c8, None),
+            Err(IllegalMove::InvalidPromotion)
+        );
+        assert_eq!(
+            game.try_move(c7, c8, Some(PieceType::King)),
+            Err(IllegalMove::InvalidPromotion)
+        );
+        let mov = game.try_move(c7, c8, Some(PieceType::Queen)).unwrap();
+        assert!(mov.check && !mov.checkmate);
+        game.undo();
+        game.move_piece(c7, c8);
+        assert_eq!(
+            try_move(&mut game, "h8", "h7"),
+            Err(IllegalMove::PromotionPending)
+        );
+
+        // Nothing after the game ends
+        game.load_fen("7k/8/8/8/8/8/8/K7 w - - 0 1").unwrap();
+        game.update_game();
+        assert_eq!(try_move(&mut game, "a1", "a2"), Err(IllegalMove::GameOver));
+        assert_eq!(
+            game.move_piece("a1".parse().unwrap(), "a2".parse().unwrap()),
+            MoveType::Invalid
+        );
+    }
+
+    #[test]
+    fn game_results() {
+        use termination::{GameResult, Outcome, Reason, TerminationError};
+
+        let mut game = Game::new();
+        game.default_board();
+        game.move_san("e4").unwrap();
+        assert_eq!(game.result(), None);
+
+        // Resigning ends the game and nothing else can happen after
+        let result = game.resign(Color::Black).unwrap();
+        assert_eq!(result.outcome, Outcome::Decisive);
+        assert_eq!(result.winner, Some(Color::White));
+        assert_eq!(result.reason, Reason::Resignation);
+        assert_eq!(result.to_pgn(), "1-0");
+        assert!(game.move_san("e5").is_err());
+        assert!(game.legal_moves().is_empty());
+        assert_eq!(game.resign(Color::White), Err(TerminationError::GameOver));
+        assert_eq!(
+            game.offer_draw(Color::White),
+            Err(TerminationError::GameOver)
+        );
+        assert!(game.to_pgn(&[]).ends_with("1. e4 1-0\n"));
+
+        // Resigning can't be taken back
+        assert_eq!(game.undo(), None);
+        assert!(game.is_over());
+        game.default_board();
+
+        // Draw offers
+        game.offer_draw(Color::White).unwrap();
+        assert_eq!(
+            game.accept_draw(Color::White),
+            Err(TerminationError::NoDrawOffer)
+        );
+        game.decline_draw(Color::Black).unwrap();
+        assert_eq!(game.draw_offer(), None);
+        game.offer_draw(Color::White).unwrap();
+        game.move_san("e4").unwrap();
+        assert_eq!(game.draw_offer(), Some(Color::White));
+        // Moving declines the offer
+        game.move_san("e5").unwrap();
+        assert_eq!(game.draw_offer(), None);
+        // Taking the move back brings the offer back, and offers after it go away
+        game.undo();
+        assert_eq!(game.draw_offer(), Some(Color::White));
+        game.move_san("e5").unwrap();
+        game.offer_draw(Color::White).unwrap();
+        game.undo();
+        game.move_san("e5").unwrap();
+        assert_eq!(game.draw_offer(), None);
+        game.offer_draw(Color::Black).unwrap();
+        let result = game.accept_draw(Color::White).unwrap();
+        assert_eq!(result, GameResult::draw(Reason::Agreement));
+        assert_eq!(result.to_pgn(), "1/2-1/2");
+        assert_eq!(game.undo(), None);
+        assert_eq!(game.result(), Some(result));
+
+        // Claiming needs a claimable status
+        game.default_board();
+        assert_eq!(game.claim_draw(), Err(TerminationError::NoDrawToClaim));
+        for san in ["Nf3", "Nf6", "Ng1", "Ng8", "Nf3", "Nf6", "Ng1", "Ng8"] {
+            game.move_san(san).unwrap();
+        }
+        assert_eq!(game.update_game(), GameStatus::ThreefoldRepetition);
+        assert_eq!(
+            game.claim_draw().unwrap().reason,
+            Reason::ThreefoldRepetition
+        );
+
+        game.default_board();
+        let result = game.timeout(Color::White).unwrap();
+        assert_eq!(
+            (result.winner, result.to_pgn()),
+            (Some(Color::Black), "0-1")
+        );
+        game.default_board();
+        assert_eq!(
+            game.abandon(Color::Black).unwrap().reason,
+            Reason::Abandonment
+        );
+        game.default_board();
+        assert_eq!(
+            game.adjudicate(None).unwrap(),
+            GameResult::draw(Reason::Adjudication)
+        );
+
+        // The board ends games through update_game
+        game.load_fen("7k/5Q2/6K1/8/8/8/8/8 w - - 0 1").unwrap();
+        game.move_san("Qg7#").unwrap();
+        assert_eq!(game.result(), None);
+        game.update_game();
+        assert_eq!(
+            game.result(),
+            Some(GameResult::win(Color::White, Reason::Checkmate))
+        );
+        assert_eq!(game.resign(Color::White), Err(TerminationError::GameOver));
+    }
+
+    #[test]
+    fn clocks() {
+        use clock::{Clock, ManualTime, Stage, TimeControl, Timing};
+        use std::{sync::Arc, time::Duration};
+        use termination::{GameResult, Reason};
+
+        let secs = Duration::from_secs;
+        let time = Arc::new(ManualTime::default());
+        let clock = |control: TimeControl| Clock::with_time_source(control, time.clone());
+
+        // Fischer adds the increment after every move
+        let mut fischer = clock(TimeControl::fischer(secs(60), secs(2)));
+        fischer.start(Color::White);
+        time.advance(secs(10));
+        assert_eq!(fischer.remaining(Color::White), secs(50));
+        fischer.press(Color::White);
+        assert_eq!(fischer.remaining(Color::White), secs(52));
+        assert_eq!(fischer.running(), Some(Color::Black));
+
+        // Bronstein gives back the time used, up to the delay
+        let mut bronstein = clock(TimeControl::bronstein(secs(60), secs(3)));
+        bronstein.start(Color::White);
+        time.advance(secs(2));
+        assert_eq!(bronstein.remaining(Color::White), secs(58));
+        bronstein.press(Color::White);
+        assert_eq!(bronstein.remaining(Color::White), secs(60));
+        time.advance(secs(5));
+        bronstein.press(Color::Black);
+        assert_eq!(bronstein.remaining(Color::Black), secs(58));
+
+        // Simple delay doesn't start counting until the delay is over
+        let mut delay = clock(TimeControl::delay(secs(60), secs(3)));
+        delay.start(Color::White);
+        time.advance(secs(2));
+        assert_eq!(delay.remaining(Color::White), secs(60));
+        time.advance(secs(3));
+        assert_eq!(delay.remaining(Color::White), secs(58));
+        delay.press(Color::White);
+        assert_eq!(delay.remaining(Color::White), secs(58));
+
+        // Stages add their time once the moves of the previous one are made
+        let control = TimeControl {
+            stages: vec![
+                Stage {
+                    moves: Some(2),
+                    time: secs(10),
+                    timing: Timing::SuddenDeath,
+                },
+                Stage {
+                    moves: None,
+                    time: secs(5),
+                    timing: Timing::Fischer(secs(1)),
+                },
+            ],
+        };
+        let mut stages = clock(control.clone());
+        for _ in 0..2 {
+            stages.start(Color::White);
+            time.advance(secs(1));
+            stages.press(Color::White);
+        }
+        assert_eq!(stages.remaining(Color::White), secs(13));
+        assert_eq!(stages.moves_to_go(Color::White), None);
+        assert_eq!(stages.moves_to_go(Color::Black), Some(2));
+
+        // PGN time controls
+        assert_eq!("2/10:5+1".parse::<TimeControl>(), Ok(control));
+        assert_eq!(
+            "40/5400+30:1800+30".parse::<TimeControl>().unwrap().stages[0],
+            Stage {
+                moves: Some(40),
+                time: secs(5400),
+                timing: Timing::Fischer(secs(30)),
+            }
+        );
+        assert_eq!("300".parse(), Ok(TimeControl::sudden_death(secs(300))));
+        for invalid in ["", "40/", "0/60", "300:40/300", "5+", "?"] {
+            assert!(invalid.parse::<TimeControl>().is_err(), "{}", invalid);
+        }
+
+        // Moves press the clock and a fallen flag ends the game
+        let mut game = Game::new();
+        game.default_board();
+        game.set_clock(clock(TimeControl::sudden_death(secs(10))));
+        game.move_san("e4").unwrap();
+        assert_eq!(game.clock().unwrap().running(), Some(Color::Black));
+        time.advance(secs(4));
+        game.move_san("e5").unwrap();
+        assert_eq!(game.clock().unwrap().remaining(Color::Black), secs(6));
+        time.advance(secs(11));
+        assert_eq!(
+            game.result(),
+            Some(GameResult::win(Color::Black, Reason::Timeout))
+        );
+        assert!(game.move_san("Nf3").is_err());
+        assert!(game.to_pgn(&[]).ends_with("1. e4 e5 0-1\n"));
+        game.update_game();
+        assert_eq!(game.clock().unwrap().running(), None);
+        assert_eq!(
+            game.resign(Color::Black),
+            Err(termination::TerminationError::GameOver)
+        );
+
+        // Running out of time against a lone king is a draw
+        game.load_fen("4k3/8/8/8/8/8/8/R3K3 w - - 0 1").unwrap();
+        game.set_clock(clock(TimeControl::sudden_death(secs(10))));
+        game.clock_mut().unwrap().start(Color::White);
+        time.advance(secs(10));
+        game.update_game();
+        assert_eq!(
+            game.result(),
+            Some(GameResult::draw(Reason::TimeoutVsInsufficientMaterial))
+        );
+        game.load_fen("4k3/8/8/8/8/8/8/R3K3 b - - 0 1").unwrap();
+        assert_eq!(
+            game.timeout(Color::Black),
+            Ok(GameResult::win(Color::White, Reason::Timeout))
+        );
+    }
+
+    #[test]
+    fn chess960() {
+        use moves::IllegalMove;
+
+        let rank = |index: u16| -> String {
+            chess960::back_rank(index)
+                .unwrap()
+                .iter()
+                .map(|piece_type| piece_type.to_char())
+                .collect()
+        };
+        assert_eq!(rank(0), "BBQNNRKR");
+        assert_eq!(rank(518), "RNBQKBNR");
+        assert_eq!(rank(959), "RKRNNQBB");
+        assert_eq!(chess960::back_rank(960), None);
+
+        // Every position is different and follows the rules
+        let mut seen = std::collections::HashSet::new();
+        for index in 0..chess960::POSITIONS {
+            let rank = rank(index);
+            let bishops: Vec<usize> = rank.match_indices('B').map(|(x, _)| x).collect();
+            let rooks: Vec<usize> = rank.match_indices('R').map(|(x, _)| x).collect();
+            let king = rank.find('K').unwrap();
+            assert!((bishops[0] + bishops[1]) % 2 == 1, "{}", rank);
+            assert!(rooks[0] < king && king < rooks[1], "{}", rank);
+            assert!(seen.insert(rank));
+        }
+
+        let mut game = Game::new();
+        assert!(game.load_chess960(518));
+        assert!(game.is_chess960());
+        assert_eq!(
+            game.to_fen(),
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w HAha - 0 1"
+        );
+        assert_eq!(game.perft(3), 8902);
+        assert!(!game.load_chess960(960));
+
+        // Kiwipete has the same counts with Chess960 castling, and a real Chess960 position
+        game.load_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w HAha - 0 1")
+            .unwrap();
+        assert_eq!(game.perft(3), 97862);
+        game.load_fen("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9")
+            .unwrap();
+        assert_eq!([1, 2, 3].map(|depth| game.perft(depth)), [21, 528, 12189]);
+
+        // The king takes its own rook and they end up on g and f, or c and d
+        game.load_fen("7k/8/8/8/8/8/8/RK4R1 w GA - 0 1").unwrap();
+        assert_eq!(game.parse_san("O-O").unwrap().to, "g1".parse().unwrap());
+        let mov = game.move_uci("b1g1").unwrap();
+        assert_eq!(mov.move_type, MoveType::Castling);
+        assert_eq!(game.to_fen(), "7k/8/8/8/8/8/8/R4RK1 b - - 1 1");
+        game.undo();
+        assert_eq!(game.to_fen(), "7k/8/8/8/8/8/8/RK4R1 w GA - 0 1");
+        assert_eq!(
+            game.to_san(game.parse_uci("b1a1").unwrap()).unwrap(),
+            "O-O-O"
+        );
+        game.move_san("O-O-O").unwrap();
+        assert_eq!(game.to_fen(), "7k/8/8/8/8/8/8/2KR2R1 b - - 1 1");
+        let pgn = game.to_pgn(&[]);
+        assert!(pgn.contains("[Variant \"Chess960\"]\n[SetUp \"1\"]\n"));
+        assert!(pgn.ends_with("1. O-O-O *\n"));
+        let games = pgn::parse_pgn(&pgn).unwrap();
+        assert_eq!(games[0].game.to_fen(), game.to_fen());
+
+        // The king can stay where it is
+        game.load_fen("4k3/8/8/8/8/8/8/6KR w H - 0 1").unwrap();
+        game.move_san("O-O").unwrap();
+        assert_eq!(game.to_fen(), "4k3/8/8/8/8/8/8/5RK1 b - - 1 1");
+
+        // The castling rook can be the only thing keeping the king out of check
+        game.load_fen("6k1/8/8/8/8/8/8/qRK5 w B - 0 1").unwrap();
+        assert_eq!(
+            game.try_move("c1".parse().unwrap(), "b1".parse().unwrap(), None),
+            Err(IllegalMove::LeavesKingInCheck)
+        );
+
+        // X-FEN uses KQ for the outermost rooks, but only when asked for Chess960
+        game.load_chess960_fen("rkr5/8/8/8/8/8/8/RKR5 w KQkq - 0 1")
+            .unwrap();
+        assert!(game.is_chess960());
+        assert_eq!(game.to_fen(), "rkr5/8/8/8/8/8/8/RKR5 w CAca - 0 1");
+        assert!(game.load_fen("rkr5/8/8/8/8/8/8/RKR5 w KC - 0 1").is_err());
+        assert_eq!(
+            game.load_fen("4k3/8/8/8/8/8/8/3K3R w K - 0 1"),
+            Err(fen::FenError::InvalidCastling("K".to_string()))
+        );
+        let games = pgn::parse_pgn(
+            "[Variant \"Chess960\"]\n[FEN \"rkr5/8/8/8/8/8/8/RKR5 w KQkq - 0 1\"]\n\n*\n",
+        )
+        .unwrap();
+        assert_eq!(games[0].game.to_fen(), "rkr5/8/8/8/8/8/8/RKR5 w CAca - 0 1");
+
+        // Outside of Chess960 the king taking its rook castles too
+        game.load_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1")
+            .unwrap();
+        assert!(!game.is_chess960());
+        let mov = game.move_uci("e1h1").unwrap();
+        assert_eq!(mov.to, "g1".parse().unwrap());
+        assert_eq!(game.to_fen(), "r3k2r/8/8/8/8/8/8/R4RK1 b kq - 1 1");
+    }
+
+    #[test]
+    fn variants() {
+        use termination::{GameResult, Reason};
+        use variant::{KingOfTheHill, RacingKings, ThreeCheck, Variant};
+
+        // King of the Hill ends as soon as a king reaches the center
+        let mut game = Game::new();
+        game.set_variant(&KingOfTheHill);
+        game.load_fen("4k3/8/8/8/8/4K3/8/8 w - - 0 1").unwrap();
+        game.update_game();
+        assert_eq!(game.status, GameStatus::Active);
+        game.move_san("Ke4").unwrap();
+        game.update_game();
+        assert_eq!(game.status, GameStatus::VariantEnd);
+        assert_eq!(
+            game.result(),
+            Some(GameResult::win(Color::White, Reason::VariantEnd))
+        );
+
+        // Three-check counts checks, and undo takes them back
+        let mut game = Game::new();
+        game.set_variant(&ThreeCheck);
+        game.default_board();
+        assert_eq!(game.to_fen(), ThreeCheck.starting_fen());
+        for san in ["e4", "e5", "Bc4", "Nc6", "Bxf7+"] {
+            game.move_san(san).unwrap();
+        }
+        assert_eq!(game.checks_given(Color::White), 1);
+        assert!(game.to_fen().contains(" 2+3 "));
+        assert_eq!(game.zobrist_key(), game.compute_zobrist());
+        game.undo();
+        assert_eq!(game.checks_given(Color::White), 0);
+
+        game.load_fen("4k3/8/8/8/8/8/8/R3K3 w - - 1+3 0 1").unwrap();
+        assert_eq!(game.checks_given(Color::White), 2);
+        game.move_san("Ra8+").unwrap();
+        game.update_game();
+        assert_eq!(
+            game.result(),
+            Some(GameResult::win(Color::White, Reason::VariantEnd))
+        );
+        assert!(game.load_fen("4k3/8/8/8/8/8/8/R3K3 w - - 4+3 0 1").is_err());
+
+        // Positions with different checks left are different positions
+        let mut other = game.clone();
+        game.load_fen("4k3/8/8/8/8/8/8/R3K3 w - - 3+3 0 1").unwrap();
+        other
+            .load_fen("4k3/8/8/8/8/8/8/R3K3 w - - 2+3 0 1")
+            .unwrap();
+        assert_ne!(game.zobrist_key(), other.zobrist_key());
+        assert_ne!(game, other);
+
+        // Checks from a promotion count when it is finished with `promote` too
+        game.load_fen("7k/P7/8/8/8/8/8/K7 w - - 3+3 0 1").unwrap();
+        game.move_piece("a7".parse().unwrap(), "a8".parse().unwrap());
+        assert_eq!(game.checks_given(Color::White), 0);
+        game.promote(PieceType::Queen);
+        assert!(game.is_color_checked(Color::Black));
+        assert_eq!(game.checks_given(Color::White), 1);
+        assert_eq!(game.zobrist_key(), game.compute_zobrist());
+        game.undo();
+        game.move_san("a8=Q+").unwrap();
+        assert_eq!(game.checks_given(Color::White), 1);
+
+        // Racing Kings never allows giving check
+        let mut game = Game::new();
+        game.set_variant(&RacingKings);
+        game.default_board();
+        assert_eq!(game.to_fen(), RacingKings.starting_fen());
+        let mut moves = MoveList::new();
+        game.generate_moves(Color::White, &mut moves);
+        assert!(moves.iter().all(|mov| !game.gives_check(*mov)));
+        assert_eq!(game.perft(1), 21);
+        assert_eq!(
+            game.try_move("e2".parse().unwrap(), "c3".parse().unwrap(), None),
+            Err(IllegalMove::VariantRule)
+        );
+
+        // Black gets one more move to draw after white arrives first
+        game.load_fen("6K1/k7/8/8/8/8/8/8 w - - 0 1").unwrap();
+        game.move_san("Kh8").unwrap();
+        game.update_game();
+        assert_eq!(game.result(), None);
+        game.move_san("Ka8").unwrap();
+        game.update_game();
+        assert_eq!(game.result(), Some(GameResult::draw(Reason::VariantEnd)));
+        game.load_fen("6K1/8/k7/8/8/8/8/8 w - - 0 1").unwrap();
+        game.move_san("Kh8").unwrap();
+        game.update_game();
+        assert_eq!(
+            game.result(),
+            Some(GameResult::win(Color::White, Reason::VariantEnd))
+        );
+
+        // The variant goes into the PGN and comes back out
+        assert_eq!(
+            variant::from_name("racing kings").unwrap().name(),
+            "Racing Kings"
+        );
+        let mut game = Game::new();
+        game.set_variant(&ThreeCheck);
+        game.default_board();
+        game.move_san("e4").unwrap();
+        let pgn = game.to_pgn(&[]);
+        assert!(pgn.contains("[Variant \"Three-check\"]\n"));
+        assert!(!pgn.contains("[FEN"));
+        let games = pgn::parse_pgn(&pgn).unwrap();
+        assert_eq!(games[0].game.variant().name(), "Three-check");
+        assert_eq!(games[0].game.to_fen(), game.to_fen());
+    }
+
+    #[test]
+    fn crazyhouse() {
+        use variant::{Crazyhouse, Variant};
+
+        let mut game = Game::new();
+        game.set_variant(&Crazyhouse);
+        game.default_board();
+        assert_eq!(game.to_fen(), Crazyhouse.starting_fen());
+        assert_eq!(game.perft(4), 197281);
+
+        // Captured pieces change sides and can be dropped back in
+        for san in ["e4", "d5", "exd5", "Qxd5", "Nc3"] {
+            game.move_san(san).unwrap();
+        }
+        assert_eq!(game.pocket(Color::White).count(PieceType::Pawn), 1);
+        assert_eq!(game.pocket(Color::Black).count(PieceType::Pawn), 1);
+        let key = game.zobrist_key();
+        assert_eq!(game.zobrist_key(), game.compute_zobrist());
+        let mov = game.move_san("P@e4").unwrap();
+        assert_eq!(mov.move_type, MoveType::Drop);
+        assert_eq!(mov.to_string(), "P@e4");
+        assert!(game.pocket(Color::Black).is_empty());
+        assert_eq!(game.zobrist_key(), game.compute_zobrist());
+        assert_eq!(
+            game.to_fen(),
+            "rnb1kbnr/ppp1pppp/8/3q4/4p3/2N5/PPPP1PPP/R1BQKBNR[P] w KQkq - 0 4"
+        );
+        game.undo();
+        assert_eq!(game.zobrist_key(), key);
+        assert_eq!(game.pocket(Color::Black).count(PieceType::Pawn), 1);
+
+        // No pawns on the back ranks, no drops on pieces and only what's in the pocket
+        game.load_fen("4k3/8/8/8/8/8/8/4K3[PNq] w - - 0 1").unwrap();
+        let square = |s: &str| s.parse().unwrap();
+        assert_eq!(
+            game.validate_drop(PieceType::Pawn, square("a8")),
+            Err(IllegalMove::InvalidDrop)
+        );
+        assert_eq!(
+            game.validate_drop(PieceType::Queen, square("a3")),
+            Err(IllegalMove::NotInPocket)
+        );
+        assert_eq!(
+            game.validate_drop(PieceType::Knight, square("e8")),
+            Err(IllegalMove::PathBlocked)
+        );
+        assert_eq!(game.perft(1), 5 + 48 + 62);
+
+        // Drops can block a check, but only in between
+        game.load_fen("4k3/8/8/8/8/8/8/q3K3[N] w - - 0 1").unwrap();
+        assert_eq!(
+            game.validate_drop(PieceType::Knight, square("e2")),
+            Err(IllegalMove::LeavesKingInCheck)
+        );
+        let drops: Vec<String> = game
+            .legal_moves()
+            .iter()
+            .filter(|mov| mov.move_type == MoveType::Drop)
+            .map(|mov| mov.to_string())
+            .collect();
+        assert_eq!(drops, ["N@b1", "N@c1", "N@d1"]);
+        assert_eq!(game.parse_uci("N@d1").unwrap().to, square("d1"));
+
+        // Something in the pocket saves a position that would be mate otherwise
+        game.load_fen("4k3/8/8/8/8/8/5PPP/r5K1[B] w - - 0 1")
+            .unwrap();
+        assert_eq!(game.update_game(), GameStatus::Check(Color::White));
+        game.load_fen("4k3/8/8/8/8/8/5PPP/r5K1 w - - 0 1").unwrap();
+        assert_eq!(game.update_game(), GameStatus::Checkmate(Color::White));
+
+        // Promoted pieces are marked in the FEN and turn back into pawns when taken
+        game.load_fen("4k3/1P6/8/8/8/8/r7/4K3[] w - - 0 1").unwrap();
+        game.move_san("b8=Q+").unwrap();
+        assert!(game.is_promoted(square("b8")));
+        assert_eq!(game.to_fen(), "1Q~2k3/8/8/8/8/8/r7/4K3[] b - - 0 1");
+        game.move_san("Kd7").unwrap();
+        game.move_san("Qb2").unwrap();
+        game.move_san("Rxb2").unwrap();
+        assert_eq!(game.pocket(Color::Black).count(PieceType::Pawn), 1);
+        assert_eq!(game.pocket(Color::Black).count(PieceType::Queen), 0);
+        game.load_fen("1Q~2k3/8/8/8/8/8/r7/4K3/qp b - - 0 1")
+            .unwrap();
+        assert!(game.is_promoted(square("b8")));
+        assert_eq!(game.to_fen(), "1Q~2k3/8/8/8/8/8/r7/4K3[qp] b - - 0 1");
+        // A promoted queen goes back as a pawn, so it isn't the same position as a real one
+        let mut real = game.clone();
+        real.load_fen("1Q2k3/8/8/8/8/8/r7/4K3[qp] b - - 0 1")
+            .unwrap();
+        assert_ne!(game, real);
+
+        // Pockets can't hold more than a game has, no matter how long the string is
+        game.load_fen("4k3/8/8/8/8/8/8/4K3[PPPPPPPPPPPPPPPPpppppppppppppppp] w - - 0 1")
+            .unwrap();
+        let fen = format!("4k3/8/8/8/8/8/8/4K3[{}] w - - 0 1", "P".repeat(300));
+        assert!(matches!(
+            game.load_fen(&fen),
+            Err(fen::FenError::InvalidPocket(_))
+        ));
+        assert!(game.load_fen("4k3/8/8/8/8/8/8/4K3[QQQ] w - - 0 1").is_err());
+        assert!(game.load_fen("4k3/8/8/8/8/8/8/4K3[K] w - - 0 1").is_err());
+
+        // The pockets and drops go through PGN
+        game.default_board();
+        for san in ["e4", "d5", "exd5", "Qxd5", "Nc3", "Qa5", "P@d5", "P@e4"] {
+            game.move_san(san).unwrap();
+        }
+        let pgn = game.to_pgn(&[]);
+        assert!(pgn.contains("[Variant \"Crazyhouse\"]\n"));
+        assert!(pgn.contains("4. P@d5 P@e4 *"));
+        let games = pgn::parse_pgn(&pgn).unwrap();
+        assert_eq!(games[0].game.to_fen(), game.to_fen());
+    }
+
+    #[test]
+    fn clock_takebacks() {
+        use clock::{Clock, ManualTime, TimeControl};
+        use std::{sync::Arc, time::Duration};
+
+        let secs = Duration::from_secs;
+        let time = Arc::new(ManualTime::default());
+
+        // Pressing out of turn still charges whoever's time was running
+        let mut clock = Clock::with_time_source(TimeControl::sudden_death(secs(60)), time.clone());
+        clock.start(Color::White);
+        time.advance(secs(30));
+        clock.press(Color::Black);
+        assert_eq!(clock.remaining(Color::White), secs(30));
+        time.advance(secs(10));
+        clock.press(Color::White);
+        assert_eq!(clock.remaining(Color::White), secs(20));
+
+        // Undo takes back the increment, the opponent's thinking time stays used
+        let mut game = Game::new();
+        game.default_board();
+        game.set_clock(Clock::with_time_source(
+            TimeControl::fischer(secs(60), secs(2)),
+            time.clone(),
+        ));
+        game.clock_mut().unwrap().start(Color::White);
+        time.advance(secs(10));
+        game.move_san("e4").unwrap();
+        assert_eq!(game.clock().unwrap().remaining(Color::White), secs(52));
+        time.advance(secs(5));
+        game.undo();
+        let clock = game.clock().unwrap();
+        assert_eq!(clock.running(), Some(Color::White));
+        assert_eq!(clock.remaining(Color::White), secs(50));
+        assert_eq!(clock.remaining(Color::Black), secs(55));
+
+        time.advance(secs(3));
+        game.move_san("e4").unwrap();
+        let clock = game.clock().unwrap();
+        assert_eq!(clock.running(), Some(Color::Black));
+        assert_eq!(clock.remaining(Color::White), secs(49));
+        assert_eq!(clock.remaining(Color::Black), secs(55));
+    }
 }
@@ -0,0 +1,238 @@
+use crate::{
+    moves::{Move, MoveList},
+    termination::{GameResult, Reason},
+    Color, Game, PieceType, Position,
+};
+
+use std::fmt;
+
+/// Starting position of standard chess
+pub const INITIAL_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+/// Rules that differ from standard chess. Every hook defaults to the standard rules,
+/// so a variant only has to override what it changes
+pub trait Variant: fmt::Debug + Send + Sync {
+    /// Name used for the PGN `Variant` tag
+    fn name(&self) -> &'static str;
+
+    fn starting_fen(&self) -> &'static str {
+        INITIAL_FEN
+    }
+
+    /// Removes moves that are legal in standard chess but not in the variant
+    fn filter_moves(&self, _game: &Game, _moves: &mut MoveList) {}
+
+    /// Called after every move is played on the board, before the turn passes. Any state
+    /// the variant keeps on the game has to be taken back by `unmake_move`
+    fn after_move(&self, _game: &mut Game, _mov: &Move) {}
+
+    /// The result if the variant's own rules end the game, checked before the standard ones
+    fn result(&self, _game: &Game) -> Option<GameResult> {
+        None
+    }
+
+    fn is_insufficient_material(&self, game: &Game) -> bool {
+        game.is_insufficient_material()
+    }
+
+    fn is_dead_position(&self, game: &Game) -> bool {
+        game.is_dead_position()
+    }
+
+    /// How many checks win the game, for variants that count them. The checks left
+    /// for each player are put in the FEN then
+    fn check_limit(&self) -> Option<u8> {
+        None
+    }
+
+    /// Whether captured pieces go to the capturer's pocket, to be dropped back on the board
+    /// as a move. The pockets are put in the FEN then
+    fn drops(&self) -> bool {
+        false
+    }
+}
+
+#[derive(Debug)]
+pub struct Standard;
+
+impl Variant for Standard {
+    fn name(&self) -> &'static str {
+        "Standard"
+    }
+}
+
+/// Getting the king to one of the four center squares wins
+#[derive(Debug)]
+pub struct KingOfTheHill;
+
+impl Variant for KingOfTheHill {
+    fn name(&self) -> &'static str {
+        "King of the Hill"
+    }
+
+    fn result(&self, game: &Game) -> Option<GameResult> {
+        let hill =
+            |position: Position| (3..=4).contains(&position.x) && (3..=4).contains(&position.y);
+        [Color::White, Color::Black]
+            .into_iter()
+            .find(|color| game.king_position(*color).is_some_and(hill))
+            .map(|color| GameResult::win(color, Reason::VariantEnd))
+    }
+
+    // A lone king can still walk up the hill
+    fn is_insufficient_material(&self, _game: &Game) -> bool {
+        false
+    }
+
+    fn is_dead_position(&self, _game: &Game) -> bool {
+        false
+    }
+}
+
+/// Checking the opponent three times wins
+#[derive(Debug)]
+pub struct ThreeCheck;
+
+impl Variant for ThreeCheck {
+    fn name(&self) -> &'static str {
+        "Three-check"
+    }
+
+    fn starting_fen(&self) -> &'static str {
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 3+3 0 1"
+    }
+
+    fn after_move(&self, game: &mut Game, mov: &Move) {
+        let color = mov.piece.color;
+        if game.is_color_checked(!color) {
+            game.checks[color as usize] += 1;
+        }
+    }
+
+    fn result(&self, game: &Game) -> Option<GameResult> {
+        [Color::White, Color::Black]
+            .into_iter()
+            .find(|color| game.checks[*color as usize] >= 3)
+            .map(|color| GameResult::win(color, Reason::VariantEnd))
+    }
+
+    // Anything but a lone king can give check
+    fn is_insufficient_material(&self, game: &Game) -> bool {
+        game.get_pieces()
+            .iter()
+            .all(|piece| piece.piece_type == PieceType::King)
+    }
+
+    fn check_limit(&self) -> Option<u8> {
+        Some(3)
+    }
+}
+
+/// Both kings race to the eighth rank, and checks aren't allowed at all. If white gets
+/// there first black has one move to catch up for a draw
+#[derive(Debug)]
+pub struct RacingKings;
+
+impl Variant for RacingKings {
+    fn name(&self) -> &'static str {
+        "Racing Kings"
+    }
+
+    fn starting_fen(&self) -> &'static str {
+        "8/8/8/8/8/8/krbnNBRK/qrbnNBRQ w - - 0 1"
+    }
+
+    fn filter_moves(&self, game: &Game, moves: &mut MoveList) {
+        moves.retain(|mov| !game.gives_check(*mov));
+    }
+
+    fn result(&self, game: &Game) -> Option<GameResult> {
+        let arrived = |color: Color| game.king_position(color).is_some_and(|king| king.y == 7);
+
+        match (arrived(Color::White), arrived(Color::Black)) {
+            (true, true) => Some(GameResult::draw(Reason::VariantEnd)),
+            (false, true) => Some(GameResult::win(Color::Black, Reason::VariantEnd)),
+            (true, false) => {
+                // Black still gets to move if its king can make it too
+                let mut moves = MoveList::new();
+                game.generate_moves(Color::Black, &mut moves);
+                let catches_up = game.current_move == Color::Black
+                    && moves
+                        .iter()
+                        .any(|mov| mov.piece.piece_type == PieceType::King && mov.to.y == 7);
+                (!catches_up).then_some(GameResult::win(Color::White, Reason::VariantEnd))
+            }
+            (false, false) => None,
+        }
+    }
+
+    // Kings can always keep racing
+    fn is_insufficient_material(&self, _game: &Game) -> bool {
+        false
+    }
+
+    fn is_dead_position(&self, _game: &Game) -> bool {
+        false
+    }
+}
+
+/// Captured pieces change sides and can be dropped back on the board instead of moving.
+/// Promoted pieces turn back into pawns when they are captured
+#[derive(Debug)]
+pub struct Crazyhouse;
+
+impl Variant for Crazyhouse {
+    fn name(&self) -> &'static str {
+        "Crazyhouse"
+    }
+
+    fn starting_fen(&self) -> &'static str {
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[] w KQkq - 0 1"
+    }
+
+    // Whatever gets captured comes back, so there is always material to mate with
+    fn is_insufficient_material(&self, _game: &Game) -> bool {
+        false
+    }
+
+    fn is_dead_position(&self, _game: &Game) -> bool {
+        false
+    }
+
+    fn drops(&self) -> bool {
+        true
+    }
+}
+
+/// Every variant there is, by their PGN names
+pub const VARIANTS: [&dyn Variant; 5] = [
+    &Standard,
+    &KingOfTheHill,
+    &ThreeCheck,
+    &RacingKings,
+    &Crazyhouse,
+];
+
+/// Looks up a variant by its PGN name, ignoring case
+pub fn from_name(name: &str) -> Option<&'static dyn Variant> {
+    VARIANTS
+        .into_iter()
+        .find(|variant| variant.name().eq_ignore_ascii_case(name))
+}
+
+impl Game {
+    pub fn variant(&self) -> &'static dyn Variant {
+        self.variant
+    }
+
+    /// Switches the rules, call `default_board` afterwards for the variant's starting position
+    pub fn set_variant(&mut self, variant: &'static dyn Variant) {
+        self.variant = variant;
+        self.zobrist = self.compute_zobrist();
+    }
+
+    /// Checks given by each player, only counted in variants that care
+    pub fn checks_given(&self, color: Color) -> u8 {
+        self.checks[color as usize]
+    }
+}